@@ -1,56 +1,463 @@
 pub const BASE_API_URL: &str = "https://live-open.biliapi.com";
 
-use crate::auth::{self, Auth};
-use reqwest::{header::HeaderMap, Client, RequestBuilder};
+use crate::{
+    apiv2::{ApiResponse, CODE_REQUEST_EXPIRED},
+    auth::{self, Auth},
+    error::{AuthError, ServiceError},
+    metrics,
+    retry::RetryPolicy,
+};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, DATE},
+    Client, RequestBuilder,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
 
 pub struct ApiAgent {
     http_client: Client,
     auth: Auth,
+    retry_policy: RetryPolicy,
+    base_url: String,
 }
 
 impl ApiAgent {
     pub fn new(auth: Auth) -> Self {
+        let retry_policy = RetryPolicy::default();
         Self {
-            http_client: reqwest::Client::new(),
+            http_client: http_client(&retry_policy),
             auth,
+            retry_policy,
+            base_url: BASE_API_URL.to_string(),
         }
     }
 
-    pub fn build_request(&self, url: String, body: String) -> RequestBuilder {
-        self.http_client
+    /// 设置接口地址，默认为BASE_API_URL
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// 设置重试策略，同时应用其中的连接及请求超时
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.http_client = http_client(&retry_policy);
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    /// 本地时间与服务端时间的偏差（秒），服务端时间 - 本地时间
+    pub fn clock_offset(&self) -> i64 {
+        self.auth.clock_offset()
+    }
+
+    /// 使用的AccessKey，作为凭证标识
+    pub fn access_key(&self) -> &str {
+        &self.auth.accesskey_id
+    }
+
+    pub fn build_request(&self, url: String, body: String) -> Result<RequestBuilder, AuthError> {
+        Ok(self
+            .http_client
             .post(url)
-            .headers(self.build_headers(body.clone()))
-            .body(body)
+            .headers(self.build_headers(body.clone())?)
+            .body(body))
     }
 
-    fn build_headers(&self, body_str: String) -> HeaderMap {
-        let mut headers = HeaderMap::new();
-        let content_md5 = auth::md5(body_str.clone());
-        headers.append(auth::HK_ACCEPT, auth::HV_ACCEPT.parse().unwrap());
-        headers.append(auth::HK_TYPE, auth::HV_TYPE.parse().unwrap());
-        self.auth.build_headers(content_md5, &mut headers);
+    /// 调用开放平台接口，path如"/v2/app/start"
+    ///
+    /// 请求体序列化为JSON并签名，按重试策略发送
+    pub async fn call<Req, Resp>(
+        &self,
+        path: &str,
+        req: &Req,
+    ) -> Result<ApiResponse<Resp>, ServiceError>
+    where
+        Req: Serialize + ?Sized,
+        Resp: DeserializeOwned,
+    {
+        self.call_with(path, req, &self.retry_policy).await
+    }
+
+    /// 调用非幂等接口，如/v2/app/start
+    ///
+    /// 请求可能已在服务端生效而客户端超时，重试会遗留前一个场次，
+    /// 因此仅在请求确定未到达服务端（连接失败）或返回可重试的返回码时重试
+    pub async fn call_non_idempotent<Req, Resp>(
+        &self,
+        path: &str,
+        req: &Req,
+    ) -> Result<ApiResponse<Resp>, ServiceError>
+    where
+        Req: Serialize + ?Sized,
+        Resp: DeserializeOwned,
+    {
+        self.post(
+            self.url(path),
+            request_body(req)?,
+            &self.retry_policy,
+            false,
+        )
+        .await
+    }
+
+    /// 使用指定的重试策略调用接口，策略中的request_timeout作用于每次请求
+    pub async fn call_with<Req, Resp>(
+        &self,
+        path: &str,
+        req: &Req,
+        policy: &RetryPolicy,
+    ) -> Result<ApiResponse<Resp>, ServiceError>
+    where
+        Req: Serialize + ?Sized,
+        Resp: DeserializeOwned,
+    {
+        self.post(self.url(path), request_body(req)?, policy, true)
+            .await
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// 按重试策略发送请求，每次尝试都会重新签名
+    ///
+    /// idempotent为false时，请求错误仅在连接失败时重试
+    async fn post<T>(
+        &self,
+        url: String,
+        body: String,
+        policy: &RetryPolicy,
+        idempotent: bool,
+    ) -> Result<ApiResponse<T>, ServiceError>
+    where
+        T: DeserializeOwned,
+    {
+        let mut attempt = 1;
+        let mut skew_retried = false;
+        loop {
+            let (res, skew_changed) = self.post_once::<T>(url.clone(), body.clone(), policy).await;
+            // 时间戳过期且已校正偏差时立即重试一次
+            let expired = matches!(&res, Ok(resp) if resp.code == CODE_REQUEST_EXPIRED);
+            if expired && skew_changed && !skew_retried {
+                skew_retried = true;
+                continue;
+            }
+            let retryable = match &res {
+                Ok(resp) => policy.is_retryable_code(resp.code),
+                Err(e) if idempotent => policy.is_retryable_error(e),
+                Err(e) => policy.is_unsent_error(e),
+            };
+            if !retryable || attempt >= policy.max_attempts {
+                return res;
+            }
+            tokio::time::sleep(policy.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    /// 发送单次请求，同时返回服务端时间偏差是否发生变化
+    async fn post_once<T>(
+        &self,
+        url: String,
+        body: String,
+        policy: &RetryPolicy,
+    ) -> (Result<ApiResponse<T>, ServiceError>, bool)
+    where
+        T: DeserializeOwned,
+    {
+        let path = url.strip_prefix(&self.base_url).unwrap_or(&url).to_string();
+        let start = Instant::now();
+        let res = match self.build_request(url, body) {
+            Ok(req) => req.timeout(policy.request_timeout).send().await,
+            Err(e) => return (Err(e.into()), false),
+        };
+        let skew_changed = match &res {
+            Ok(res) => self.observe_date(res.headers()),
+            Err(_) => false,
+        };
+        let res = async {
+            let res = res?.error_for_status()?.text().await?;
+            Ok(serde_json::from_str::<ApiResponse<T>>(&res)?)
+        }
+        .await;
+        metrics::api_call(&path, start, &res);
+        (res, skew_changed)
+    }
+
+    /// 读取响应的Date头更新时间偏差
+    fn observe_date(&self, headers: &HeaderMap) -> bool {
         headers
+            .get(DATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok())
+            .map(|t| self.auth.observe_server_time(t))
+            .unwrap_or(false)
+    }
+
+    fn build_headers(&self, body_str: String) -> Result<HeaderMap, AuthError> {
+        let content_md5 = auth::md5(body_str);
+        let mut headers = self.auth.build_headers(content_md5)?;
+        headers.append(auth::HK_ACCEPT, HeaderValue::from_static(auth::HV_ACCEPT));
+        headers.append(auth::HK_TYPE, HeaderValue::from_static(auth::HV_TYPE));
+        Ok(headers)
+    }
+}
+
+/// 按AccessKey管理多组凭证的ApiAgent
+pub(crate) struct AgentPool {
+    default_key: String,
+    agents: RwLock<HashMap<String, Arc<ApiAgent>>>,
+}
+
+impl AgentPool {
+    pub fn new(agent: ApiAgent) -> Self {
+        let default_key = agent.access_key().to_string();
+        let agents = HashMap::from([(default_key.clone(), Arc::new(agent))]);
+        Self {
+            default_key,
+            agents: RwLock::new(agents),
+        }
+    }
+
+    pub fn default_key(&self) -> &str {
+        &self.default_key
     }
+
+    pub fn insert(&self, agent: ApiAgent) {
+        let key = agent.access_key().to_string();
+        self.agents.write().unwrap().insert(key, Arc::new(agent));
+    }
+
+    /// 获取凭证对应的ApiAgent，key为空时使用默认凭证
+    pub fn get(&self, key: &str) -> Option<Arc<ApiAgent>> {
+        let key = if key.is_empty() {
+            &self.default_key
+        } else {
+            key
+        };
+        self.agents.read().unwrap().get(key).cloned()
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.agents.read().unwrap().keys().cloned().collect()
+    }
+}
+
+fn http_client(policy: &RetryPolicy) -> Client {
+    Client::builder()
+        .connect_timeout(policy.connect_timeout)
+        .timeout(policy.request_timeout)
+        .build()
+        .expect("failed to build http client")
+}
+
+/// 序列化请求体，ApiAgent::call使用
+pub(crate) fn request_body<Req: Serialize + ?Sized>(req: &Req) -> Result<String, ServiceError> {
+    Ok(serde_json::to_string(req)?)
 }
 
 pub fn apiurl(url: &str) -> String {
     format!("{}{}", BASE_API_URL, url)
 }
 
+/// 开启场次成功的响应体
+#[cfg(test)]
+pub(crate) fn test_start_body(game_id: &str) -> String {
+    serde_json::json!({
+        "code": 0,
+        "message": "",
+        "data": {
+            "game_info": { "game_id": game_id },
+            "websocket_info": { "auth_body": "auth", "wss_link": ["wss://link"] },
+            "anchor_info": { "room_id": 1, "uname": "anchor", "uface": "", "uid": 1 },
+        },
+    })
+    .to_string()
+}
+
+/// 按顺序返回responses（Date头, 响应体）的本地服务，返回接口地址，结束后返回收到的请求行及请求头
+#[cfg(test)]
+pub(crate) fn test_server(
+    responses: Vec<(Option<String>, String)>,
+) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for (date, body) in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+            let mut len = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                let lower = line.to_ascii_lowercase();
+                if let Some(v) = lower.strip_prefix("content-length:") {
+                    len = v.trim().parse().unwrap();
+                }
+                head.push_str(&lower);
+            }
+            reader.read_exact(&mut vec![0u8; len]).unwrap();
+            requests.push(head);
+            let date = date.map(|d| format!("Date: {}\r\n", d)).unwrap_or_default();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                date,
+                body.len(),
+                body
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        }
+        requests
+    });
+    (base_url, handle)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        agent::ApiAgent, apiv2::V2apis, auth::Auth, env_access_key, env_access_secret, env_app_id,
-        env_live_code,
+        agent::{test_server, test_start_body, ApiAgent},
+        apiv2::{V2apis, CODE_OK, CODE_REQUEST_COOLDOWN, CODE_REQUEST_EXPIRED, CODE_SERVICE_ERROR},
+        auth::{Auth, FixedClock},
+        config::Config,
+        error::ServiceError,
+        retry::RetryPolicy,
     };
+    use std::{
+        net::TcpListener,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    fn code_body(code: u32) -> String {
+        format!(r#"{{"code":{},"message":"","data":{{}}}}"#, code)
+    }
+
+    fn test_agent() -> ApiAgent {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        ApiAgent::new(Auth::new("key", "secret").unwrap()).with_retry_policy(policy)
+    }
+
+    #[tokio::test]
+    async fn test_retry() {
+        let (base_url, server) = test_server(vec![
+            (None, code_body(CODE_SERVICE_ERROR)),
+            (None, code_body(CODE_OK)),
+        ]);
+        let agent = test_agent().with_base_url(base_url);
+        let res = agent.end(1, "game".to_string()).await.unwrap();
+        assert_eq!(res.code, CODE_OK);
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_start_retry() {
+        let start = test_start_body("game");
+        let cooldown = format!(
+            r#"{{"code":{},"message":"","data":null}}"#,
+            CODE_REQUEST_COOLDOWN
+        );
+        let (base_url, server) = test_server(vec![(None, cooldown), (None, start)]);
+        let agent = test_agent().with_base_url(base_url);
+        let res = agent.start("code".to_string(), 1).await.unwrap();
+        assert_eq!(res.data.unwrap().game_info.game_id, "game");
+        // 每次尝试重新签名
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|r| r.starts_with("post /v2/app/start ")));
+        let nonce = |r: &String| {
+            r.lines()
+                .find(|l| l.starts_with("x-bili-signature-nonce:"))
+                .map(str::to_string)
+        };
+        assert_ne!(nonce(&requests[0]), nonce(&requests[1]));
+
+        // 连接失败时请求未到达服务端，可以重试
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let agent = test_agent().with_base_url(base_url);
+        let err = agent.start("code".to_string(), 1).await.unwrap_err();
+        assert!(agent.retry_policy().is_unsent_error(&err));
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        // 只建立连接不返回响应
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/v2/app/heartbeat", listener.local_addr().unwrap());
+        let policy = RetryPolicy {
+            request_timeout: Duration::from_millis(200),
+            ..RetryPolicy::none()
+        };
+        let agent = ApiAgent::new(Auth::new("key", "secret").unwrap()).with_retry_policy(policy);
+        let err = agent
+            .build_request(url, "{}".to_string())
+            .unwrap()
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+        // 请求已发送，非幂等接口不重试
+        let err = ServiceError::ReqwestError(err);
+        assert!(agent.retry_policy().is_retryable_error(&err));
+        assert!(!agent.retry_policy().is_unsent_error(&err));
+        // 失败原因中保留底层错误
+        let reason = err.to_string();
+        assert!(reason.starts_with("Reqwest error: "), "{}", reason);
+        assert!(reason.contains("timed out"), "{}", reason);
+        drop(listener);
+    }
+
+    #[tokio::test]
+    async fn test_skew_retry() {
+        let local = 1_700_000_000;
+        let server = httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(local + 600));
+        let (base_url, server) = test_server(vec![
+            (Some(server.clone()), code_body(CODE_REQUEST_EXPIRED)),
+            (Some(server), code_body(CODE_OK)),
+        ]);
+        let auth = Auth::new("key", "secret")
+            .unwrap()
+            .with_clock(FixedClock(local));
+        let agent = ApiAgent::new(auth).with_base_url(base_url);
+        let res = agent.end(1, "game".to_string()).await.unwrap();
+        assert_eq!(res.code, CODE_OK);
+        assert_eq!(agent.clock_offset(), 600);
+        // 仅重新签名发送一次，时间戳按服务端时间校正
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains(&format!("x-bili-timestamp: {}\r\n", local)));
+        assert!(requests[1].contains(&format!("x-bili-timestamp: {}\r\n", local + 600)));
+    }
 
     #[tokio::test]
     async fn test_api_start() {
-        let _code = env_live_code();
-        let _agent = ApiAgent::new(Auth::new(env_access_key(), env_access_secret()));
-        let _res = _agent.start(_code, env_app_id()).await;
-        if _res.is_ok() {
-            let r = _res.unwrap();
+        let config = Config::from_dotenv().unwrap();
+        let _code = config.code().unwrap().to_string();
+        let _agent = ApiAgent::new(config.auth().unwrap());
+        let _res = _agent.start(_code, config.app_id).await;
+        if let Ok(r) = _res {
             println!("ApiResponse:{} {}", r.code, r.message);
             if let Some(data) = r.data {
                 println!("game_info.game_id:{}", data.game_info.game_id);
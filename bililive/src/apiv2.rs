@@ -1,21 +1,47 @@
-use crate::{
-    agent::{apiurl, ApiAgent},
-    error::ServiceError,
-};
+use crate::{agent::ApiAgent, error::ServiceError};
 use async_trait::async_trait;
+use bililivecmd::{proto::UserIdentity, Secret};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+
+/// 公共返回码
+pub const CODE_OK: u32 = 0;
+/// 参数错误
+pub const CODE_INVALID_PARAMS: u32 = 4000;
+/// 应用无效
+pub const CODE_INVALID_APP: u32 = 4001;
+/// 签名异常
+pub const CODE_INVALID_SIGN: u32 = 4002;
+/// 请求过期（时间戳偏差过大）
+pub const CODE_REQUEST_EXPIRED: u32 = 4003;
+/// 服务异常
+pub const CODE_SERVICE_ERROR: u32 = 5000;
+/// 请求冷却期
+pub const CODE_REQUEST_COOLDOWN: u32 = 7001;
+/// 心跳过期或game_id错误
+pub const CODE_HEARTBEAT_EXPIRED: u32 = 7003;
+/// 身份码错误
+pub const CODE_INVALID_CODE: u32 = 7007;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct ApiResponse<T>
-where
-    T: Serialize + Default,
-{
+pub struct ApiResponse<T> {
     pub code: u32,
     pub data: Option<T>,
     pub message: String,
 }
 
+impl<T> ApiResponse<T> {
+    /// 返回码非0时转换为ApiRejected错误
+    pub fn into_result(self) -> Result<Option<T>, ServiceError> {
+        if self.code != CODE_OK {
+            return Err(ServiceError::ApiRejected {
+                code: self.code,
+                message: self.message,
+            });
+        }
+        Ok(self.data)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct StartData {
     pub anchor_info: AnchorInfo,
@@ -23,6 +49,27 @@ pub struct StartData {
     pub websocket_info: WebSocketInfo,
 }
 
+impl StartData {
+    /// 检查场次及长连信息是否完整
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        if self.game_info.game_id.is_empty() {
+            return Err(ServiceError::MalformedStartData("empty game_id"));
+        }
+        if self.websocket_info.auth_body.expose().is_empty() {
+            return Err(ServiceError::MalformedStartData("empty auth_body"));
+        }
+        if self.websocket_info.wss_link.iter().all(|l| l.is_empty()) {
+            return Err(ServiceError::MalformedStartData("empty wss_link"));
+        }
+        Ok(())
+    }
+
+    /// 首个可用的长连地址
+    pub fn server_url(&self) -> Option<&String> {
+        self.websocket_info.wss_link.iter().find(|l| !l.is_empty())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GameInfo {
     pub game_id: String,
@@ -30,7 +77,7 @@ pub struct GameInfo {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct WebSocketInfo {
-    pub auth_body: String,
+    pub auth_body: Secret<String>,
     pub wss_link: Vec<String>,
 }
 
@@ -40,6 +87,46 @@ pub struct AnchorInfo {
     pub uname: String,
     pub uface: String,
     pub uid: u64,
+    /// 主播唯一标识，uid废弃后使用
+    #[serde(default)]
+    pub open_id: String,
+    /// 主播在同一开发者下的唯一标识
+    #[serde(default)]
+    pub union_id: String,
+}
+
+impl UserIdentity for AnchorInfo {
+    fn uid(&self) -> i64 {
+        self.uid as i64
+    }
+    fn open_id(&self) -> &str {
+        &self.open_id
+    }
+    fn union_id(&self) -> &str {
+        &self.union_id
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct StartRequest {
+    pub code: String,
+    pub app_id: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct EndRequest {
+    pub app_id: i64,
+    pub game_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct HeartBeatRequest {
+    pub game_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BatchHeartBeatRequest {
+    pub game_ids: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -53,11 +140,16 @@ pub struct BatchHeartBeatData {
     pub failed_game_ids: Vec<String>,
 }
 
+/// 请求结构体只包含字符串及数字字段，序列化不会失败
+fn to_json<Req: Serialize>(req: &Req) -> String {
+    serde_json::to_string(req).unwrap_or_default()
+}
+
 #[async_trait]
 pub trait V2apis {
     const START_URL: &'static str = "/v2/app/start";
     fn start_json(code: String, app_id: i64) -> String {
-        json!({"code":code, "app_id":app_id}).to_string()
+        to_json(&StartRequest { code, app_id })
     }
     async fn start(
         &self,
@@ -67,20 +159,20 @@ pub trait V2apis {
 
     const END_URL: &'static str = "/v2/app/end";
     fn end_json(app_id: i64, game_id: String) -> String {
-        json!({"app_id":app_id, "game_id":game_id}).to_string()
+        to_json(&EndRequest { app_id, game_id })
     }
     async fn end(&self, app_id: i64, game_id: String)
         -> Result<ApiResponse<EndData>, ServiceError>;
 
     const HEARTBEAT_URL: &'static str = "/v2/app/heartbeat";
     fn heartbeat_json(game_id: String) -> String {
-        json!({"game_id":game_id}).to_string()
+        to_json(&HeartBeatRequest { game_id })
     }
     async fn heartbeat(&self, game_id: String) -> Result<ApiResponse<HeartBeatData>, ServiceError>;
 
     const BATCHHEARTBEAT_URL: &'static str = "/v2/app/batchHeartbeat";
     fn batch_heartbeat_json(game_ids: Vec<String>) -> String {
-        json!({"game_ids":game_ids}).to_string()
+        to_json(&BatchHeartBeatRequest { game_ids })
     }
     async fn batch_heartbeat(
         &self,
@@ -95,11 +187,8 @@ impl V2apis for ApiAgent {
         code: String,
         app_id: i64,
     ) -> Result<ApiResponse<StartData>, ServiceError> {
-        let req_body = Self::start_json(code, app_id);
-        let url = apiurl(Self::START_URL);
-        let req = self.build_request(url, req_body);
-        let res = req.send().await?.text().await?;
-        Ok(serde_json::from_str::<ApiResponse<StartData>>(&res)?)
+        self.call_non_idempotent(Self::START_URL, &StartRequest { code, app_id })
+            .await
     }
 
     async fn end(
@@ -107,45 +196,60 @@ impl V2apis for ApiAgent {
         app_id: i64,
         game_id: String,
     ) -> Result<ApiResponse<EndData>, ServiceError> {
-        let req_body = Self::end_json(app_id, game_id);
-        let url = apiurl(Self::END_URL);
-        let req = self.build_request(url, req_body);
-        let res = req.send().await?.text().await?;
-        Ok(serde_json::from_str::<ApiResponse<EndData>>(&res)?)
+        self.call(Self::END_URL, &EndRequest { app_id, game_id })
+            .await
     }
 
     async fn heartbeat(&self, game_id: String) -> Result<ApiResponse<HeartBeatData>, ServiceError> {
-        let req_body = Self::heartbeat_json(game_id);
-        let url = apiurl(Self::HEARTBEAT_URL);
-        let req = self.build_request(url, req_body);
-        let res = req.send().await?.text().await?;
-        Ok(serde_json::from_str::<ApiResponse<HeartBeatData>>(&res)?)
+        self.call(Self::HEARTBEAT_URL, &HeartBeatRequest { game_id })
+            .await
     }
 
     async fn batch_heartbeat(
         &self,
         game_ids: Vec<String>,
     ) -> Result<ApiResponse<BatchHeartBeatData>, ServiceError> {
-        let req_body = Self::batch_heartbeat_json(game_ids);
-        let url = apiurl(Self::BATCHHEARTBEAT_URL);
-        let req = self.build_request(url, req_body);
-        let res = req.send().await?.text().await?;
-        Ok(serde_json::from_str::<ApiResponse<BatchHeartBeatData>>(
-            &res,
-        )?)
+        self.call(
+            Self::BATCHHEARTBEAT_URL,
+            &BatchHeartBeatRequest { game_ids },
+        )
+        .await
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{ApiResponse, StartData, V2apis};
-    use crate::agent::ApiAgent;
+    use super::{
+        AnchorInfo, ApiResponse, BatchHeartBeatRequest, EndRequest, StartData, StartRequest,
+        V2apis, CODE_INVALID_CODE,
+    };
+    use crate::{
+        agent::{request_body, ApiAgent},
+        error::ServiceError,
+    };
     use serde_json::json;
 
     #[test]
     fn test_start_request_json() {
         let json = ApiAgent::start_json("code".to_string(), 1234567890123);
         println!("{}", json);
+        let start = StartRequest {
+            code: "code".to_string(),
+            app_id: 1234567890123,
+        };
+        assert_eq!(json, request_body(&start).unwrap());
+        assert_eq!(
+            ApiAgent::end_json(1, "game".to_string()),
+            r#"{"app_id":1,"game_id":"game"}"#
+        );
+        assert_eq!(
+            ApiAgent::heartbeat_json("game".to_string()),
+            r#"{"game_id":"game"}"#
+        );
+        assert_eq!(
+            ApiAgent::batch_heartbeat_json(vec!["a".to_string()]),
+            r#"{"game_ids":["a"]}"#
+        );
     }
 
     #[test]
@@ -181,11 +285,81 @@ mod tests {
                     // 主播头像
                     "uface": "",
                     // 主播uid
-                    "uid": 0
+                    "uid": 0,
+                    // 主播open_id
+                    "open_id": "",
+                    // 主播union_id
+                    "union_id": ""
                 }
             }
         });
         let json_str = serde_json::to_string(&json_obj).unwrap();
         println!("{}", json_str);
     }
+
+    #[test]
+    fn test_request_json() {
+        let start = StartRequest {
+            code: "code".to_string(),
+            app_id: 1234567890123,
+        };
+        let json = request_body(&start).unwrap();
+        assert_eq!(json, r#"{"code":"code","app_id":1234567890123}"#);
+        let end = EndRequest {
+            app_id: 1,
+            game_id: "game".to_string(),
+        };
+        assert_eq!(
+            request_body(&end).unwrap(),
+            r#"{"app_id":1,"game_id":"game"}"#
+        );
+        let batch = BatchHeartBeatRequest {
+            game_ids: vec!["a".to_string(), "b".to_string()],
+        };
+        assert_eq!(request_body(&batch).unwrap(), r#"{"game_ids":["a","b"]}"#);
+    }
+
+    #[test]
+    fn test_anchor_info_serde() {
+        let anchor: AnchorInfo =
+            serde_json::from_str(r#"{"room_id":1,"uname":"a","uface":"","uid":2}"#).unwrap();
+        assert_eq!((anchor.room_id, anchor.uid), (1, 2));
+        assert!(anchor.open_id.is_empty());
+        assert!(serde_json::from_str::<AnchorInfo>(r#"{"room_id":1,"uname":"a"}"#).is_err());
+    }
+
+    #[test]
+    fn test_into_result() {
+        let resp = ApiResponse::<StartData> {
+            code: CODE_INVALID_CODE,
+            message: "invalid code".to_string(),
+            ..Default::default()
+        };
+        let err = resp.into_result().unwrap_err();
+        assert_eq!(err.api_code(), Some(CODE_INVALID_CODE));
+        let resp = ApiResponse::<StartData> {
+            data: Some(StartData::default()),
+            ..Default::default()
+        };
+        assert!(resp.into_result().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_start_data_validate() {
+        let mut data = StartData::default();
+        assert!(matches!(
+            data.validate(),
+            Err(ServiceError::MalformedStartData("empty game_id"))
+        ));
+        data.game_info.game_id = "game".to_string();
+        data.websocket_info.auth_body = "{}".into();
+        data.websocket_info.wss_link = vec!["".to_string()];
+        assert!(matches!(
+            data.validate(),
+            Err(ServiceError::MalformedStartData("empty wss_link"))
+        ));
+        data.websocket_info.wss_link.push("wss://link".to_string());
+        assert!(data.validate().is_ok());
+        assert_eq!(data.server_url().unwrap(), "wss://link");
+    }
 }
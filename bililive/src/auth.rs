@@ -11,62 +11,223 @@ pub const HK_BILI_SIGNATURE_NONCE: &str = "x-bili-signature-nonce";
 pub const HK_BILI_ACCESSKEYID: &str = "x-bili-accesskeyid";
 pub const HK_BILI_SIGNATURE_VERSION: &str = "x-bili-signature-version";
 pub const HV_BILI_SIGNATURE_VERSION: &str = "1.0";
+/// H5启动参数签名字段
+pub const H5_CODE_SIGN: &str = "CodeSign";
+/// H5启动参数有效期默认值
+pub const H5_MAX_AGE: Duration = Duration::from_secs(300);
+/// 服务端时间精度为秒，偏差在此范围内视为无偏差
+pub const CLOCK_SKEW_TOLERANCE_SECS: i64 = 1;
 
+use crate::error::AuthError;
+use bililivecmd::Secret;
 use data_encoding::HEXLOWER;
 use rand::prelude::*;
-use reqwest::header::HeaderMap;
+use reqwest::header::{HeaderMap, HeaderValue};
 use ring::hmac;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// 签名使用的时间来源
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// 签名使用的随机数来源
+pub trait NonceSource: Debug + Send + Sync {
+    fn nonce(&self) -> String;
+}
+
+/// 系统时间
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// 固定时间，值为Unix时间戳（秒），用于测试及复现签名
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub u64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.0)
+    }
+}
+
+/// 随机数
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomNonce;
+
+impl NonceSource for RandomNonce {
+    fn nonce(&self) -> String {
+        let mut rng = rand::thread_rng();
+        rng.gen::<usize>().to_string()
+    }
+}
+
+/// 固定随机数，用于测试及复现签名
+#[derive(Debug, Clone)]
+pub struct FixedNonce(pub String);
+
+impl NonceSource for FixedNonce {
+    fn nonce(&self) -> String {
+        self.0.clone()
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Auth {
     pub accesskey_id: String,
-    pub accesskey_secret: String,
+    pub accesskey_secret: Secret<String>,
+    clock: Arc<dyn Clock>,
+    nonce_source: Arc<dyn NonceSource>,
+    clock_offset: Arc<AtomicI64>,
 }
 
 impl Auth {
-    pub fn new(key_id: impl Into<String>, key_secret: impl Into<String>) -> Self {
-        Self {
-            accesskey_id: key_id.into().clone(),
-            accesskey_secret: key_secret.into().clone(),
+    /// 校验并建立凭证
+    pub fn new(
+        key_id: impl Into<String>,
+        key_secret: impl Into<String>,
+    ) -> Result<Self, AuthError> {
+        Self::with_secret(key_id, Secret::new(key_secret.into()))
+    }
+
+    /// 使用已包装的密钥建立凭证，不产生明文副本
+    pub fn with_secret(
+        key_id: impl Into<String>,
+        key_secret: Secret<String>,
+    ) -> Result<Self, AuthError> {
+        let auth = Self {
+            accesskey_id: key_id.into(),
+            accesskey_secret: key_secret,
+            clock: Arc::new(SystemClock),
+            nonce_source: Arc::new(RandomNonce),
+            clock_offset: Arc::new(AtomicI64::new(0)),
+        };
+        auth.validate()?;
+        Ok(auth)
+    }
+
+    /// 设置签名使用的时间来源
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// 设置签名使用的随机数来源
+    pub fn with_nonce_source(mut self, nonce_source: impl NonceSource + 'static) -> Self {
+        self.nonce_source = Arc::new(nonce_source);
+        self
+    }
+
+    /// 当前签名时间戳（秒），已按服务端时间校正
+    pub fn timestamp(&self) -> u64 {
+        let local = self.local_timestamp() as i64;
+        (local + self.clock_offset()).max(0) as u64
+    }
+
+    fn local_timestamp(&self) -> u64 {
+        self.clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+
+    /// 本地时间与服务端时间的偏差（秒），服务端时间 - 本地时间
+    pub fn clock_offset(&self) -> i64 {
+        self.clock_offset.load(Ordering::Relaxed)
+    }
+
+    pub fn set_clock_offset(&self, offset: i64) {
+        self.clock_offset.store(offset, Ordering::Relaxed);
+    }
+
+    /// 根据服务端时间更新偏差，返回偏差是否发生变化
+    pub fn observe_server_time(&self, server_time: SystemTime) -> bool {
+        let server = match server_time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() as i64,
+            Err(_) => return false,
+        };
+        let mut offset = server - self.local_timestamp() as i64;
+        if offset.abs() <= CLOCK_SKEW_TOLERANCE_SECS {
+            offset = 0;
         }
+        self.clock_offset.swap(offset, Ordering::Relaxed) != offset
     }
 
-    pub fn build_headers(&self, content_md5: String, headers: &mut HeaderMap) {
-        headers.append(HK_BILI_CONTENT_MD5, content_md5.parse().unwrap());
-        let timestamp = timestamp();
-        headers.append(HK_BILI_TIMESTAMP, timestamp.parse().unwrap());
+    /// 校验AccessKey及Secret
+    pub fn validate(&self) -> Result<(), AuthError> {
+        validate_credential("access key", &self.accesskey_id)?;
+        validate_credential("access secret", self.accesskey_secret.expose())?;
+        header_value(HK_BILI_ACCESSKEYID, &self.accesskey_id)?;
+        Ok(())
+    }
+
+    /// 构建签名请求头
+    pub fn build_headers(&self, content_md5: String) -> Result<HeaderMap, AuthError> {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            HK_BILI_CONTENT_MD5,
+            header_value(HK_BILI_CONTENT_MD5, &content_md5)?,
+        );
+        let timestamp = self.timestamp().to_string();
+        headers.append(
+            HK_BILI_TIMESTAMP,
+            header_value(HK_BILI_TIMESTAMP, &timestamp)?,
+        );
         headers.append(
             HK_BILI_SIGNATURE_METHOD,
-            HV_BILI_SIGNATURE_METHOD.parse().unwrap(),
+            HeaderValue::from_static(HV_BILI_SIGNATURE_METHOD),
+        );
+        let nonce = self.nonce_source.nonce();
+        headers.append(
+            HK_BILI_SIGNATURE_NONCE,
+            header_value(HK_BILI_SIGNATURE_NONCE, &nonce)?,
+        );
+        headers.append(
+            HK_BILI_ACCESSKEYID,
+            header_value(HK_BILI_ACCESSKEYID, &self.accesskey_id)?,
         );
-        let nonce = nonce();
-        headers.append(HK_BILI_SIGNATURE_NONCE, nonce.parse().unwrap());
-        headers.append(HK_BILI_ACCESSKEYID, self.accesskey_id.parse().unwrap());
         headers.append(
             HK_BILI_SIGNATURE_VERSION,
-            HV_BILI_SIGNATURE_VERSION.parse().unwrap(),
+            HeaderValue::from_static(HV_BILI_SIGNATURE_VERSION),
         );
         let sign_str = builder_sign_str(self.accesskey_id.clone(), content_md5, nonce, timestamp);
-        let sign_str = sign(sign_str, self.accesskey_secret.clone());
-        headers.append(HK_AUTHORIZATION, sign_str.parse().unwrap());
+        let sign_str = sign_bytes(&sign_str, self.accesskey_secret.expose().as_bytes());
+        headers.append(HK_AUTHORIZATION, header_value(HK_AUTHORIZATION, &sign_str)?);
+        Ok(headers)
     }
 }
 
-pub fn md5(content: String) -> String {
-    let hash = md5::compute(content);
-    format!("{:x}", hash)
+fn validate_credential(name: &'static str, value: &str) -> Result<(), AuthError> {
+    if value.is_empty() {
+        return Err(AuthError::Empty(name));
+    }
+    if value.trim() != value {
+        return Err(AuthError::Whitespace(name));
+    }
+    Ok(())
 }
 
-fn nonce() -> String {
-    let mut rng = rand::thread_rng();
-    rng.gen::<usize>().to_string()
+fn header_value(name: &'static str, value: &str) -> Result<HeaderValue, AuthError> {
+    HeaderValue::from_str(value).map_err(|_| AuthError::InvalidHeaderValue(name))
 }
 
-fn timestamp() -> String {
-    let now = SystemTime::now();
-    let duration = now.duration_since(UNIX_EPOCH).unwrap();
-    duration.as_secs().to_string()
+pub fn md5(content: String) -> String {
+    let hash = md5::compute(content);
+    format!("{:x}", hash)
 }
 
 fn builder_sign_str(
@@ -92,12 +253,162 @@ fn builder_sign_str(
     )
 }
 
+/// HMAC-SHA256签名
 pub fn sign(sign_str: String, secret: impl Into<String>) -> String {
-    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.into().as_bytes());
+    sign_bytes(&sign_str, Secret::new(secret.into()).expose().as_bytes())
+}
+
+/// HMAC-SHA256签名，直接使用密钥字节，不复制密钥
+pub fn sign_bytes(sign_str: &str, secret: &[u8]) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
     let tag = hmac::sign(&key, sign_str.as_bytes());
     HEXLOWER.encode(tag.as_ref())
 }
 
+/// H5插件启动参数
+#[derive(Debug, Clone, Default)]
+pub struct H5LaunchParams {
+    /// 主播身份码
+    pub code: String,
+    /// 主播uid
+    pub mid: u64,
+    /// 签名时间戳（秒）
+    pub timestamp: u64,
+    /// 主播房间号
+    pub room_id: u64,
+    /// 启动来源
+    pub caller: String,
+}
+
+/// H5插件启动参数验签
+///
+/// 除CodeSign外的参数按key升序拼接为k=v&k=v，使用AccessKeySecret进行HMAC-SHA256签名
+#[derive(Debug, Clone)]
+pub struct H5Verifier {
+    secret: Secret<String>,
+    max_age: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl H5Verifier {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self {
+            secret: Secret::new(secret.into()),
+            max_age: H5_MAX_AGE,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// 设置参数有效期
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// 设置校验时间戳使用的时间来源
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// 计算参数签名，忽略CodeSign
+    pub fn sign_params<K, V>(&self, params: impl IntoIterator<Item = (K, V)>) -> String
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let params = collect_params(params);
+        sign_bytes(&h5_sign_str(&params), self.secret.expose().as_bytes())
+    }
+
+    /// 校验签名及时间戳，返回解析后的启动参数
+    pub fn verify<K, V>(
+        &self,
+        params: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<H5LaunchParams, AuthError>
+    where
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        let params = collect_params(params);
+        let code_sign = params
+            .get(H5_CODE_SIGN)
+            .ok_or(AuthError::MissingParam(H5_CODE_SIGN))?;
+        let expected = sign_bytes(&h5_sign_str(&params), self.secret.expose().as_bytes());
+        if !constant_time_eq(
+            expected.as_bytes(),
+            code_sign.to_ascii_lowercase().as_bytes(),
+        ) {
+            return Err(AuthError::InvalidSignature);
+        }
+
+        let launch = H5LaunchParams {
+            code: param(&params, "Code")?.to_string(),
+            mid: parse_param(&params, "Mid")?,
+            timestamp: parse_param(&params, "Timestamp")?,
+            room_id: parse_param(&params, "RoomId")?,
+            caller: params.get("Caller").cloned().unwrap_or_default(),
+        };
+        let now = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if now.abs_diff(launch.timestamp) > self.max_age.as_secs() {
+            return Err(AuthError::Expired {
+                timestamp: launch.timestamp,
+                now,
+            });
+        }
+        Ok(launch)
+    }
+}
+
+fn collect_params<K, V>(params: impl IntoIterator<Item = (K, V)>) -> BTreeMap<String, String>
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    params
+        .into_iter()
+        .map(|(k, v)| (k.as_ref().to_string(), v.as_ref().to_string()))
+        .collect()
+}
+
+fn h5_sign_str(params: &BTreeMap<String, String>) -> String {
+    params
+        .iter()
+        .filter(|(k, _)| k.as_str() != H5_CODE_SIGN)
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+/// 比较签名，耗时与不同字节的位置无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn param<'a>(
+    params: &'a BTreeMap<String, String>,
+    name: &'static str,
+) -> Result<&'a str, AuthError> {
+    params
+        .get(name)
+        .map(|v| v.as_str())
+        .ok_or(AuthError::MissingParam(name))
+}
+
+fn parse_param<T: std::str::FromStr>(
+    params: &BTreeMap<String, String>,
+    name: &'static str,
+) -> Result<T, AuthError> {
+    param(params, name)?
+        .parse()
+        .map_err(|_| AuthError::InvalidParam(name))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -126,6 +437,138 @@ mod tests {
         println!("{}", content_md5);
     }
 
+    #[test]
+    fn test_auth_validate() {
+        assert!(matches!(Auth::new("", "secret"), Err(AuthError::Empty(_))));
+        assert!(matches!(
+            Auth::new("key ", "secret"),
+            Err(AuthError::Whitespace(_))
+        ));
+        assert!(matches!(
+            Auth::new("key\u{7f}", "secret"),
+            Err(AuthError::InvalidHeaderValue(_))
+        ));
+        let auth = Auth::new("key", "JzOzZfSHeYYnAMZ").unwrap();
+        assert!(!format!("{:?}", auth).contains("JzOzZfSHeYYnAMZ"));
+        let headers = auth.build_headers(md5("{}".to_string())).unwrap();
+        assert_eq!(headers.get(HK_BILI_ACCESSKEYID).unwrap(), "key");
+        assert_eq!(headers.get(HK_AUTHORIZATION).unwrap().len(), 64);
+    }
+
+    #[test]
+    fn test_golden_headers() {
+        let auth = Auth::new("xxxx", "JzOzZfSHeYYnAMZ")
+            .unwrap()
+            .with_clock(FixedClock(1624594467))
+            .with_nonce_source(FixedNonce(
+                "ad184c09-095f-91c3-0849-230dd3744045".to_string(),
+            ));
+        let headers = auth
+            .build_headers("fa6837e35b2f591865b288dfd859ce9d".to_string())
+            .unwrap();
+        let expected = [
+            (HK_BILI_CONTENT_MD5, "fa6837e35b2f591865b288dfd859ce9d"),
+            (HK_BILI_TIMESTAMP, "1624594467"),
+            (HK_BILI_SIGNATURE_METHOD, HV_BILI_SIGNATURE_METHOD),
+            (
+                HK_BILI_SIGNATURE_NONCE,
+                "ad184c09-095f-91c3-0849-230dd3744045",
+            ),
+            (HK_BILI_ACCESSKEYID, "xxxx"),
+            (HK_BILI_SIGNATURE_VERSION, HV_BILI_SIGNATURE_VERSION),
+            (
+                HK_AUTHORIZATION,
+                "a81c50234b6bbf15bc56e387ee4f19c6f871af2f70b837dc56db16517d4a341f",
+            ),
+        ];
+        assert_eq!(headers.len(), expected.len());
+        for (k, v) in expected {
+            assert_eq!(headers.get(k).unwrap(), v);
+        }
+    }
+
+    #[test]
+    fn test_clock_offset() {
+        let auth = Auth::new("key", "secret")
+            .unwrap()
+            .with_clock(FixedClock(1000));
+        assert_eq!(auth.timestamp(), 1000);
+        assert!(!auth.observe_server_time(UNIX_EPOCH + Duration::from_secs(1001)));
+        assert_eq!(auth.clock_offset(), 0);
+        assert!(auth.observe_server_time(UNIX_EPOCH + Duration::from_secs(1300)));
+        assert_eq!(auth.clock_offset(), 300);
+        assert_eq!(auth.timestamp(), 1300);
+        let headers = auth.clone().build_headers(md5("".to_string())).unwrap();
+        assert_eq!(headers.get(HK_BILI_TIMESTAMP).unwrap(), "1300");
+        assert!(auth.observe_server_time(UNIX_EPOCH + Duration::from_secs(900)));
+        assert_eq!(auth.timestamp(), 900);
+    }
+
+    #[test]
+    fn test_h5_verify() {
+        let verifier = H5Verifier::new("secret").with_clock(FixedClock(1700000100));
+        let mut params = vec![
+            ("Code", "ABCDEF".to_string()),
+            ("Mid", "123".to_string()),
+            ("Timestamp", "1700000000".to_string()),
+            ("RoomId", "456".to_string()),
+            ("Caller", "bilibili".to_string()),
+        ];
+        assert_eq!(
+            h5_sign_str(&collect_params(params.clone())),
+            "Caller=bilibili&Code=ABCDEF&Mid=123&RoomId=456&Timestamp=1700000000"
+        );
+        let code_sign = verifier.sign_params(params.clone());
+        params.push((H5_CODE_SIGN, code_sign.clone()));
+        let launch = verifier.verify(params.clone()).unwrap();
+        assert_eq!(launch.code, "ABCDEF");
+        assert_eq!(launch.room_id, 456);
+        assert_eq!(verifier.sign_params(params.clone()), code_sign);
+
+        let mut tampered = params.clone();
+        tampered[0].1 = "FEDCBA".to_string();
+        assert!(matches!(
+            verifier.verify(tampered),
+            Err(AuthError::InvalidSignature)
+        ));
+        assert!(matches!(
+            verifier.verify(params[..5].to_vec()),
+            Err(AuthError::MissingParam(H5_CODE_SIGN))
+        ));
+        let verifier = verifier.with_clock(FixedClock(1700001000));
+        assert!(matches!(
+            verifier.verify(params),
+            Err(AuthError::Expired { .. })
+        ));
+    }
+
+    #[test]
+    fn test_h5_known_vector() {
+        // 签名值由独立的HMAC-SHA256实现（Python hmac）计算
+        let params = [
+            ("Caller", "bilibili"),
+            ("Code", "BBS3RAHXNXQ36"),
+            ("Mid", "110000345"),
+            ("Platform", "pc"),
+            ("RoomId", "21552918"),
+            ("Timestamp", "1650012983"),
+            (
+                H5_CODE_SIGN,
+                "e1c4f641523720e232009d13b79d02f3b65ebbba0a98a35a28c24db566856d67",
+            ),
+        ];
+        let launch = H5Verifier::new("JzOzZfSHeYYnAMZ")
+            .with_clock(FixedClock(1650012983))
+            .verify(params)
+            .unwrap();
+        assert_eq!(launch.code, "BBS3RAHXNXQ36");
+        assert_eq!(launch.mid, 110000345);
+        assert_eq!(launch.room_id, 21552918);
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"ab"));
+    }
+
     #[test]
     fn test_signature_data() {
         let str = builder_sign_str(
@@ -135,11 +578,12 @@ mod tests {
             "1624594467",
         );
         println!("{}", str);
-        let sign = sign(str, "JzOzZfSHeYYnAMZ");
+        let sign = sign(str.clone(), "JzOzZfSHeYYnAMZ");
         println!("{}", sign);
         assert_eq!(
             sign,
             "a81c50234b6bbf15bc56e387ee4f19c6f871af2f70b837dc56db16517d4a341f"
         );
+        assert_eq!(sign_bytes(&str, b"JzOzZfSHeYYnAMZ"), sign);
     }
 }
@@ -0,0 +1,502 @@
+use crate::{
+    auth::Auth,
+    error::ConfigError,
+    heartbeat::{HeartbeatConfig, MAX_BATCH_SIZE, MAX_HEARTBEAT_INTERVAL},
+    project::StartPolicy,
+    retry::RetryPolicy,
+};
+use bililivecmd::Secret;
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path, time::Duration};
+
+/// SDK配置
+///
+/// 可从环境变量、.env文件或TOML文件加载，加载时校验所有字段
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub access_key: String,
+    pub access_secret: Secret<String>,
+    pub app_id: i64,
+    /// 主播身份码
+    pub code: Option<String>,
+    /// 数据库连接，如sqlite://data.db
+    pub database_url: Option<String>,
+    pub heartbeat: HeartbeatConfig,
+    /// 心跳失败后是否自动重新开启场次并重连
+    pub auto_recover: bool,
+    pub start_policy: StartPolicy,
+    /// API请求重试策略
+    pub retry_policy: RetryPolicy,
+}
+
+/// 配置文件格式
+///
+/// ```toml
+/// access_key = "key"
+/// access_secret = "secret"
+/// app_id = 123
+/// code = "code"
+/// database_url = "sqlite://data.db?mode=rwc"
+///
+/// [heartbeat]
+/// interval_secs = 20
+/// jitter_secs = 2
+/// batch_size = 200
+/// retry_interval_secs = 5
+///
+/// [project]
+/// auto_recover = true
+/// start_policy = "reuse"
+///
+/// [retry]
+/// max_attempts = 3
+/// base_delay_ms = 500
+/// max_delay_ms = 5000
+/// connect_timeout_ms = 5000
+/// request_timeout_ms = 10000
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    access_key: Option<String>,
+    access_secret: Option<String>,
+    app_id: Option<i64>,
+    code: Option<String>,
+    database_url: Option<String>,
+    #[serde(default)]
+    heartbeat: RawHeartbeat,
+    #[serde(default)]
+    project: RawProject,
+    #[serde(default)]
+    retry: RawRetry,
+    #[serde(skip)]
+    source: Source,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawHeartbeat {
+    interval_secs: Option<u64>,
+    jitter_secs: Option<u64>,
+    batch_size: Option<usize>,
+    retry_interval_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawProject {
+    auto_recover: Option<bool>,
+    start_policy: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRetry {
+    max_attempts: Option<u32>,
+    base_delay_ms: Option<u64>,
+    max_delay_ms: Option<u64>,
+    connect_timeout_ms: Option<u64>,
+    request_timeout_ms: Option<u64>,
+}
+
+impl Config {
+    /// 从环境变量加载
+    ///
+    /// ACCESS_KEY ACCESS_SECRET APP_ID LIVE_CODE DATABASE_URL
+    /// HEARTBEAT_INTERVAL HEARTBEAT_JITTER HEARTBEAT_BATCH_SIZE HEARTBEAT_RETRY_INTERVAL
+    /// AUTO_RECOVER START_POLICY RETRY_MAX_ATTEMPTS RETRY_BASE_DELAY_MS RETRY_MAX_DELAY_MS
+    /// RETRY_CONNECT_TIMEOUT_MS RETRY_REQUEST_TIMEOUT_MS
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_lookup(|k| std::env::var(k).ok())
+    }
+
+    /// 加载当前目录的.env文件后从环境变量加载，已存在的环境变量优先
+    pub fn from_dotenv() -> Result<Self, ConfigError> {
+        match dotenvy::dotenv() {
+            Ok(_) => {}
+            Err(e) if e.not_found() => {}
+            Err(e) => return Err(e.into()),
+        }
+        Self::from_env()
+    }
+
+    /// 仅从指定的.env文件加载，不读取也不修改环境变量
+    pub fn from_env_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let vars = dotenvy::from_path_iter(path)?.collect::<Result<HashMap<_, _>, _>>()?;
+        Self::from_lookup(|k| vars.get(k).cloned())
+    }
+
+    /// 从TOML文件加载
+    pub fn from_toml_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_toml_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml_str(s: &str) -> Result<Self, ConfigError> {
+        toml::from_str::<RawConfig>(s)?.into_config()
+    }
+
+    /// 使用自定义的变量读取方式加载，变量名同from_env
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let var = |k: &str| lookup(k).filter(|v| !v.is_empty());
+        let raw = RawConfig {
+            access_key: var("ACCESS_KEY"),
+            access_secret: var("ACCESS_SECRET"),
+            app_id: parse_var(&var, "APP_ID")?,
+            code: var("LIVE_CODE"),
+            database_url: var("DATABASE_URL"),
+            heartbeat: RawHeartbeat {
+                interval_secs: parse_var(&var, "HEARTBEAT_INTERVAL")?,
+                jitter_secs: parse_var(&var, "HEARTBEAT_JITTER")?,
+                batch_size: parse_var(&var, "HEARTBEAT_BATCH_SIZE")?,
+                retry_interval_secs: parse_var(&var, "HEARTBEAT_RETRY_INTERVAL")?,
+            },
+            project: RawProject {
+                auto_recover: parse_var(&var, "AUTO_RECOVER")?,
+                start_policy: var("START_POLICY"),
+            },
+            retry: RawRetry {
+                max_attempts: parse_var(&var, "RETRY_MAX_ATTEMPTS")?,
+                base_delay_ms: parse_var(&var, "RETRY_BASE_DELAY_MS")?,
+                max_delay_ms: parse_var(&var, "RETRY_MAX_DELAY_MS")?,
+                connect_timeout_ms: parse_var(&var, "RETRY_CONNECT_TIMEOUT_MS")?,
+                request_timeout_ms: parse_var(&var, "RETRY_REQUEST_TIMEOUT_MS")?,
+            },
+            source: Source::Env,
+        };
+        raw.into_config()
+    }
+
+    /// 使用配置的凭证构建Auth
+    pub fn auth(&self) -> Result<Auth, ConfigError> {
+        Ok(Auth::with_secret(
+            self.access_key.clone(),
+            self.access_secret.clone(),
+        )?)
+    }
+
+    /// 主播身份码，未配置时返回错误
+    pub fn code(&self) -> Result<&str, ConfigError> {
+        self.code.as_deref().ok_or(ConfigError::Missing("code"))
+    }
+
+    /// 数据库连接，未配置时返回错误
+    pub fn database_url(&self) -> Result<&str, ConfigError> {
+        self.database_url
+            .as_deref()
+            .ok_or(ConfigError::Missing("database_url"))
+    }
+}
+
+fn parse_var<T: std::str::FromStr>(
+    var: &dyn Fn(&str) -> Option<String>,
+    key: &'static str,
+) -> Result<Option<T>, ConfigError> {
+    var(key)
+        .map(|v| {
+            v.trim().parse::<T>().map_err(|_| ConfigError::Invalid {
+                key,
+                reason: format!("cannot parse {:?}", v),
+            })
+        })
+        .transpose()
+}
+
+fn invalid(key: &'static str, reason: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        key,
+        reason: reason.into(),
+    }
+}
+
+/// 配置来源，错误信息中使用对应来源的键名
+#[derive(Debug, Default, Clone, Copy)]
+enum Source {
+    #[default]
+    Toml,
+    Env,
+}
+
+/// TOML键名与环境变量名
+const ENV_KEYS: [(&str, &str); 14] = [
+    ("access_key", "ACCESS_KEY"),
+    ("access_secret", "ACCESS_SECRET"),
+    ("app_id", "APP_ID"),
+    ("database_url", "DATABASE_URL"),
+    ("heartbeat.interval_secs", "HEARTBEAT_INTERVAL"),
+    ("heartbeat.jitter_secs", "HEARTBEAT_JITTER"),
+    ("heartbeat.batch_size", "HEARTBEAT_BATCH_SIZE"),
+    ("heartbeat.retry_interval_secs", "HEARTBEAT_RETRY_INTERVAL"),
+    ("project.start_policy", "START_POLICY"),
+    ("retry.max_attempts", "RETRY_MAX_ATTEMPTS"),
+    ("retry.base_delay_ms", "RETRY_BASE_DELAY_MS"),
+    ("retry.max_delay_ms", "RETRY_MAX_DELAY_MS"),
+    ("retry.connect_timeout_ms", "RETRY_CONNECT_TIMEOUT_MS"),
+    ("retry.request_timeout_ms", "RETRY_REQUEST_TIMEOUT_MS"),
+];
+
+impl Source {
+    fn key(self, toml_key: &'static str) -> &'static str {
+        match self {
+            Source::Toml => toml_key,
+            Source::Env => ENV_KEYS
+                .iter()
+                .find(|(k, _)| *k == toml_key)
+                .map(|(_, e)| *e)
+                .unwrap_or(toml_key),
+        }
+    }
+}
+
+impl RawConfig {
+    fn into_config(self) -> Result<Config, ConfigError> {
+        let source = self.source;
+        let k = |key: &'static str| source.key(key);
+        let access_key = self
+            .access_key
+            .ok_or(ConfigError::Missing(k("access_key")))?;
+        let access_secret = self
+            .access_secret
+            .map(Secret::new)
+            .ok_or(ConfigError::Missing(k("access_secret")))?;
+        Auth::with_secret(access_key.clone(), access_secret.clone())?;
+        let app_id = self.app_id.ok_or(ConfigError::Missing(k("app_id")))?;
+        if app_id <= 0 {
+            return Err(invalid(k("app_id"), "must be positive"));
+        }
+        if matches!(&self.database_url, Some(url) if url.trim().is_empty()) {
+            return Err(invalid(k("database_url"), "empty"));
+        }
+
+        let default = HeartbeatConfig::default();
+        let hb = self.heartbeat;
+        let interval = hb
+            .interval_secs
+            .map(Duration::from_secs)
+            .unwrap_or(default.interval);
+        let heartbeat = HeartbeatConfig {
+            interval,
+            // 未设置抖动时按间隔缩小默认值，只校验显式设置的抖动
+            jitter: hb
+                .jitter_secs
+                .map(Duration::from_secs)
+                .unwrap_or(default.jitter.min(interval / 2)),
+            batch_size: hb.batch_size.unwrap_or(default.batch_size),
+            retry_interval: hb
+                .retry_interval_secs
+                .map(Duration::from_secs)
+                .unwrap_or(default.retry_interval),
+        };
+        if heartbeat.interval.is_zero() || heartbeat.interval > MAX_HEARTBEAT_INTERVAL {
+            return Err(invalid(
+                k("heartbeat.interval_secs"),
+                format!("must be within 1..={}", MAX_HEARTBEAT_INTERVAL.as_secs()),
+            ));
+        }
+        if hb.jitter_secs.is_some() && heartbeat.jitter >= heartbeat.interval {
+            return Err(invalid(
+                k("heartbeat.jitter_secs"),
+                format!("must be less than {}", k("heartbeat.interval_secs")),
+            ));
+        }
+        if heartbeat.batch_size == 0 || heartbeat.batch_size > MAX_BATCH_SIZE {
+            return Err(invalid(
+                k("heartbeat.batch_size"),
+                format!("must be within 1..={}", MAX_BATCH_SIZE),
+            ));
+        }
+        if heartbeat.retry_interval.is_zero() || heartbeat.retry_interval > MAX_HEARTBEAT_INTERVAL {
+            return Err(invalid(
+                k("heartbeat.retry_interval_secs"),
+                format!("must be within 1..={}", MAX_HEARTBEAT_INTERVAL.as_secs()),
+            ));
+        }
+
+        let project = self.project;
+        let start_policy = match project.start_policy.as_deref().map(str::to_ascii_lowercase) {
+            None => StartPolicy::default(),
+            Some(p) if p == "reuse" => StartPolicy::Reuse,
+            Some(p) if p == "replace" => StartPolicy::Replace,
+            Some(p) => {
+                return Err(invalid(
+                    k("project.start_policy"),
+                    format!("unknown policy {:?}, expected reuse or replace", p),
+                ))
+            }
+        };
+        let rc = self.retry;
+        let default = RetryPolicy::default();
+        let retry_policy = RetryPolicy {
+            max_attempts: rc.max_attempts.unwrap_or(default.max_attempts),
+            base_delay: rc
+                .base_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.base_delay),
+            max_delay: rc
+                .max_delay_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_delay),
+            connect_timeout: rc
+                .connect_timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.connect_timeout),
+            request_timeout: rc
+                .request_timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.request_timeout),
+            ..default
+        };
+        if retry_policy.max_attempts == 0 {
+            return Err(invalid(k("retry.max_attempts"), "must be at least 1"));
+        }
+        if retry_policy.base_delay > retry_policy.max_delay {
+            return Err(invalid(
+                k("retry.base_delay_ms"),
+                format!("must not exceed {}", k("retry.max_delay_ms")),
+            ));
+        }
+        if retry_policy.connect_timeout.is_zero() {
+            return Err(invalid(k("retry.connect_timeout_ms"), "must be positive"));
+        }
+        if retry_policy.request_timeout.is_zero() {
+            return Err(invalid(k("retry.request_timeout_ms"), "must be positive"));
+        }
+
+        Ok(Config {
+            access_key,
+            access_secret,
+            app_id,
+            code: self.code,
+            database_url: self.database_url,
+            heartbeat,
+            auto_recover: project.auto_recover.unwrap_or(false),
+            start_policy,
+            retry_policy,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::{error::ConfigError, project::StartPolicy};
+    use std::{collections::HashMap, time::Duration};
+
+    #[test]
+    fn test_config_toml() {
+        let config = Config::from_toml_str(
+            r#"
+            access_key = "key"
+            access_secret = "secret"
+            app_id = 123
+            database_url = "sqlite::memory:"
+
+            [heartbeat]
+            interval_secs = 30
+
+            [project]
+            auto_recover = true
+            start_policy = "replace"
+
+            [retry]
+            max_attempts = 4
+            request_timeout_ms = 2000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.app_id, 123);
+        assert_eq!(config.heartbeat.interval, Duration::from_secs(30));
+        assert_eq!(config.start_policy, StartPolicy::Replace);
+        assert!(config.auto_recover);
+        assert_eq!(config.retry_policy.max_attempts, 4);
+        assert_eq!(config.retry_policy.request_timeout, Duration::from_secs(2));
+        assert!(config.code().is_err());
+        assert!(!format!("{:?}", config).contains("\"secret\""));
+
+        let err = Config::from_toml_str("access_key = \"key\"").unwrap_err();
+        assert!(matches!(err, ConfigError::Missing("access_secret")));
+        let err = Config::from_toml_str(
+            "access_key = \"key\"\naccess_secret = \"s\"\napp_id = 1\n[heartbeat]\ninterval_secs = 60",
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::Invalid {
+                key: "heartbeat.interval_secs",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_config_lookup() {
+        let vars = HashMap::from([
+            ("ACCESS_KEY", "key"),
+            ("ACCESS_SECRET", "secret"),
+            ("APP_ID", "123"),
+            ("LIVE_CODE", "code"),
+            ("RETRY_MAX_ATTEMPTS", "5"),
+            ("RETRY_REQUEST_TIMEOUT_MS", "3000"),
+        ]);
+        let config = Config::from_lookup(|k| vars.get(k).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.code().unwrap(), "code");
+        assert_eq!(config.retry_policy.max_attempts, 5);
+        assert_eq!(config.retry_policy.request_timeout, Duration::from_secs(3));
+        assert!(config.auth().is_ok());
+
+        let config = Config::from_lookup(|k| match k {
+            "APP_ID" => Some("abc".to_string()),
+            _ => vars.get(k).map(|v| v.to_string()),
+        });
+        assert!(matches!(
+            config,
+            Err(ConfigError::Invalid { key: "APP_ID", .. })
+        ));
+        // 校验错误使用环境变量名
+        let config = Config::from_lookup(|k| match k {
+            "HEARTBEAT_INTERVAL" => Some("60".to_string()),
+            _ => vars.get(k).map(|v| v.to_string()),
+        });
+        assert!(matches!(
+            config,
+            Err(ConfigError::Invalid {
+                key: "HEARTBEAT_INTERVAL",
+                ..
+            })
+        ));
+        let config = Config::from_lookup(|k| match k {
+            "ACCESS_SECRET" => None,
+            _ => vars.get(k).map(|v| v.to_string()),
+        });
+        assert!(matches!(config, Err(ConfigError::Missing("ACCESS_SECRET"))));
+    }
+
+    #[test]
+    fn test_config_interval_only() {
+        let vars = HashMap::from([
+            ("ACCESS_KEY", "key"),
+            ("ACCESS_SECRET", "secret"),
+            ("APP_ID", "123"),
+        ]);
+        // 未设置抖动时默认值不超过间隔的一半
+        for (interval, jitter) in [("1", 500), ("2", 1000), ("30", 2000)] {
+            let config = Config::from_lookup(|k| match k {
+                "HEARTBEAT_INTERVAL" => Some(interval.to_string()),
+                _ => vars.get(k).map(|v| v.to_string()),
+            })
+            .unwrap();
+            assert_eq!(config.heartbeat.jitter, Duration::from_millis(jitter));
+        }
+        let config = Config::from_lookup(|k| match k {
+            "HEARTBEAT_INTERVAL" => Some("2".to_string()),
+            "HEARTBEAT_JITTER" => Some("2".to_string()),
+            _ => vars.get(k).map(|v| v.to_string()),
+        });
+        assert!(matches!(
+            config,
+            Err(ConfigError::Invalid {
+                key: "HEARTBEAT_JITTER",
+                ..
+            })
+        ));
+    }
+}
@@ -1,11 +1,67 @@
 use thiserror::Error;
 
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("Empty {0}")]
+    Empty(&'static str),
+    #[error("Invalid {0}: leading or trailing whitespace")]
+    Whitespace(&'static str),
+    #[error("Invalid header value: {0}")]
+    InvalidHeaderValue(&'static str),
+    #[error("Missing param: {0}")]
+    MissingParam(&'static str),
+    #[error("Invalid param: {0}")]
+    InvalidParam(&'static str),
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("Expired timestamp:{timestamp} now:{now}")]
+    Expired { timestamp: u64, now: u64 },
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Config io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Config env file error: {0}")]
+    Dotenv(#[from] dotenvy::Error),
+    #[error("Config toml error: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Missing config: {0}")]
+    Missing(&'static str),
+    #[error("Invalid config {key}: {reason}")]
+    Invalid { key: &'static str, reason: String },
+    #[error("Invalid credential: {0}")]
+    Auth(#[from] AuthError),
+}
+
 #[derive(Error, Debug)]
 pub enum ServiceError {
     #[error("unknown error")]
     Unknown,
-    #[error("Reqwest error")]
+    #[error("Reqwest error: {0}")]
     ReqwestError(#[from] reqwest::Error),
-    #[error("API Deserialize error")]
+    #[error("API Deserialize error: {0}")]
     APIDeserializeError(#[from] serde_json::Error),
+    #[error("API rejected code:{code} message:{message}")]
+    ApiRejected { code: u32, message: String },
+    #[error("Auth error: {0}")]
+    AuthError(#[from] AuthError),
+    #[error("Request timeout")]
+    Timeout,
+    #[error("Unknown credential: {0}")]
+    UnknownCredential(String),
+    #[error("Quota exceeded app_id:{app_id} limit:{limit}")]
+    QuotaExceeded { app_id: i64, limit: usize },
+    #[error("Malformed start data: {0}")]
+    MalformedStartData(&'static str),
+}
+
+impl ServiceError {
+    /// API返回的错误码
+    pub fn api_code(&self) -> Option<u32> {
+        match self {
+            ServiceError::ApiRejected { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
 }
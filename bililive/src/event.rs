@@ -0,0 +1,86 @@
+use crate::{apiv2::AnchorInfo, GameRoom};
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+
+/// 服务生命周期事件
+#[derive(Debug, Clone)]
+pub enum ApiServiceEvent {
+    /// 场次已开启
+    ProjectStarted {
+        room: GameRoom,
+        anchor_info: AnchorInfo,
+    },
+    /// 心跳成功
+    HeartbeatOk { game_ids: Vec<String> },
+    /// 心跳请求失败，将在重试间隔后再次发送
+    HeartbeatFailed {
+        game_ids: Vec<String>,
+        reason: String,
+    },
+    /// 场次已结束，error为结束接口的错误信息
+    ProjectEnded {
+        room: GameRoom,
+        error: Option<String>,
+    },
+    /// 场次心跳失败或已过期，已从服务中移除
+    ProjectExpired { room: GameRoom, reason: String },
+    /// 场次已使用身份码重新开启，长连接代理已重新认证
+    ProjectRecovered { old_game_id: String, room: GameRoom },
+    /// 场次重新开启失败
+    ProjectRecoverFailed { room: GameRoom, reason: String },
+}
+
+/// 服务事件处理
+///
+/// 事件在心跳任务及结束场次时按顺序依次分发，handle需尽快返回，不能阻塞或等待网络请求，
+/// 耗时的处理应转发到通道（如ChannelEventHandle）或自行spawn任务
+#[async_trait]
+pub trait ApiServiceEventHandle: Send + Sync {
+    async fn handle(&self, event: ApiServiceEvent);
+}
+
+/// 将事件转发到通道
+pub struct ChannelEventHandle {
+    sender: mpsc::UnboundedSender<ApiServiceEvent>,
+}
+
+impl ChannelEventHandle {
+    pub fn new() -> (Self, mpsc::UnboundedReceiver<ApiServiceEvent>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (Self { sender }, receiver)
+    }
+}
+
+#[async_trait]
+impl ApiServiceEventHandle for ChannelEventHandle {
+    async fn handle(&self, event: ApiServiceEvent) {
+        let _ = self.sender.send(event);
+    }
+}
+
+pub(crate) type EventHandles = Arc<RwLock<Vec<Arc<dyn ApiServiceEventHandle>>>>;
+
+/// 分发事件到所有处理对象，依次等待每个处理对象以保证事件顺序
+pub(crate) async fn emit(handles: &EventHandles, event: ApiServiceEvent) {
+    for handle in handles.read().await.iter() {
+        handle.handle(event.clone()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_channel_handle() {
+        let (handle, mut receiver) = ChannelEventHandle::new();
+        let handles: EventHandles = Arc::new(RwLock::new(vec![Arc::new(handle)]));
+        let game_ids = vec!["game".to_string()];
+        emit(&handles, ApiServiceEvent::HeartbeatOk { game_ids }).await;
+        match receiver.recv().await {
+            Some(ApiServiceEvent::HeartbeatOk { game_ids }) => assert_eq!(game_ids, ["game"]),
+            e => panic!("unexpected event {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,436 @@
+use crate::{
+    agent::{AgentPool, ApiAgent},
+    apiv2::{
+        ApiResponse, BatchHeartBeatData, BatchHeartBeatRequest, HeartBeatData, HeartBeatRequest,
+        V2apis, CODE_HEARTBEAT_EXPIRED, CODE_OK,
+    },
+    error::ServiceError,
+    event::{emit, ApiServiceEvent, EventHandles},
+    metrics,
+    project::{agent_params, start_session},
+    rooms::GameRooms,
+    GameRoom,
+};
+use rand::Rng;
+use std::{collections::HashMap, sync::Arc};
+use tokio::{
+    sync::Notify,
+    task::JoinSet,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+/// 场次无心跳自动关闭的时间
+pub const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+/// 单次心跳请求的时限，包含重试
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 心跳间隔上限，间隔加心跳时限仍小于SESSION_TIMEOUT，确保场次不会过期
+pub const MAX_HEARTBEAT_INTERVAL: Duration = SESSION_TIMEOUT
+    .saturating_sub(HEARTBEAT_TIMEOUT)
+    .saturating_sub(Duration::from_secs(10));
+/// 批量心跳单次最多的场次数
+pub const MAX_BATCH_SIZE: usize = 200;
+
+/// 项目心跳配置
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    /// 心跳间隔
+    pub interval: Duration,
+    /// 随机抖动，实际间隔为 interval ± jitter
+    pub jitter: Duration,
+    /// 批量心跳单次的场次数
+    pub batch_size: usize,
+    /// 心跳请求失败后的重试间隔
+    pub retry_interval: Duration,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(20),
+            jitter: Duration::from_secs(2),
+            batch_size: MAX_BATCH_SIZE,
+            retry_interval: Duration::from_secs(5),
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    /// 下一次心跳的等待时间，不超过MAX_HEARTBEAT_INTERVAL
+    pub fn next_delay(&self) -> Duration {
+        let jitter = self.jitter.min(self.interval);
+        let delay = if jitter.is_zero() {
+            self.interval
+        } else {
+            let offset = rand::thread_rng().gen_range(0..=jitter.as_millis() as u64 * 2);
+            (self.interval - jitter) + Duration::from_millis(offset)
+        };
+        delay.clamp(Duration::from_secs(1), MAX_HEARTBEAT_INTERVAL)
+    }
+
+    /// 失败后的重试等待时间
+    pub fn retry_delay(&self) -> Duration {
+        self.retry_interval
+            .clamp(Duration::from_secs(1), MAX_HEARTBEAT_INTERVAL)
+    }
+
+    /// 按批量大小拆分场次
+    pub fn chunks(&self, game_ids: Vec<String>) -> Vec<Vec<String>> {
+        let size = self.batch_size.clamp(1, MAX_BATCH_SIZE);
+        game_ids.chunks(size).map(|c| c.to_vec()).collect()
+    }
+}
+
+/// 项目心跳任务
+pub(crate) struct HeartbeatContext {
+    pub agents: Arc<AgentPool>,
+    pub game_rooms: Arc<GameRooms>,
+    pub event_handles: EventHandles,
+    pub auto_recover: bool,
+    pub config: HeartbeatConfig,
+    pub notify: Arc<Notify>,
+}
+
+/// 单次心跳结果
+#[derive(Default)]
+struct BeatResult {
+    ok: Vec<String>,
+    failed: Vec<String>,
+    expired: Vec<(String, String)>,
+}
+
+impl HeartbeatContext {
+    pub async fn run(self: Arc<Self>) {
+        // 每个场次下一次心跳的时间
+        let mut schedule: HashMap<String, Instant> = HashMap::new();
+        // 心跳请求在独立任务中并发发送，慢请求不会推迟其它场次的心跳
+        let mut beating = JoinSet::new();
+        // 场次恢复在独立任务中执行，避免阻塞其它场次的心跳，心跳任务结束时一并取消
+        let mut recovering = JoinSet::new();
+        loop {
+            while recovering.try_join_next().is_some() {}
+            let now = Instant::now();
+            let rooms = self.game_rooms.snapshot().await;
+            schedule.retain(|k, _| rooms.contains_key(k));
+            for k in rooms.keys() {
+                // 新场次立即发送首次心跳
+                schedule.entry(k.clone()).or_insert(now);
+            }
+            // 抖动范围内即将到期的场次合并发送
+            let window = now + self.config.jitter;
+            let due = schedule
+                .iter()
+                .filter(|(_, t)| **t <= window)
+                .map(|(k, _)| k.clone())
+                .collect::<Vec<String>>();
+            if !due.is_empty() {
+                // 发送中的场次在心跳时限后才会再次到期，任务异常退出时按失败重试
+                let pending = now + HEARTBEAT_TIMEOUT + self.config.retry_delay();
+                for k in &due {
+                    schedule.insert(k.clone(), pending);
+                }
+                let this = Arc::clone(&self);
+                beating.spawn(async move { this.beat(&rooms, due).await });
+            }
+            let next = schedule
+                .values()
+                .min()
+                .copied()
+                .unwrap_or_else(|| Instant::now() + self.config.next_delay());
+            tokio::select! {
+                _ = tokio::time::sleep_until(next) => {}
+                _ = self.notify.notified() => {}
+                Some(Ok(result)) = beating.join_next() => {
+                    let now = Instant::now();
+                    // 发送期间已移除的场次不再调度
+                    for k in result.ok {
+                        if let Some(t) = schedule.get_mut(&k) {
+                            *t = now + self.config.next_delay();
+                        }
+                    }
+                    for k in result.failed {
+                        if let Some(t) = schedule.get_mut(&k) {
+                            *t = now + self.config.retry_delay();
+                        }
+                    }
+                    for (game_id, reason) in result.expired {
+                        schedule.remove(&game_id);
+                        if let Some(room) = self.expire(game_id, reason).await {
+                            recovering.spawn(Arc::clone(&self).recover(room));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 按凭证分组发送心跳
+    async fn beat(&self, rooms: &HashMap<String, GameRoom>, game_ids: Vec<String>) -> BeatResult {
+        let mut groups: HashMap<&str, Vec<String>> = HashMap::new();
+        for id in game_ids {
+            let key = rooms.get(&id).map(|r| r.access_key.as_str()).unwrap_or("");
+            groups.entry(key).or_default().push(id);
+        }
+        let mut result = BeatResult::default();
+        let mut futures = Vec::new();
+        for (key, ids) in groups {
+            let Some(agent) = self.agents.get(key) else {
+                self.failed(ids.clone(), format!("unknown credential {}", key))
+                    .await;
+                result.failed.extend(ids);
+                continue;
+            };
+            futures.push(async move { self.beat_group(&agent, ids).await });
+        }
+        for r in futures::future::join_all(futures).await {
+            result.ok.extend(r.ok);
+            result.failed.extend(r.failed);
+            result.expired.extend(r.expired);
+        }
+        result
+    }
+
+    async fn beat_group(&self, agent: &ApiAgent, game_ids: Vec<String>) -> BeatResult {
+        if game_ids.len() == 1 {
+            return self.beat_single(agent, game_ids[0].clone()).await;
+        }
+        let futures = self
+            .config
+            .chunks(game_ids)
+            .into_iter()
+            .map(|chunk| self.beat_batch(agent, chunk));
+        let mut result = BeatResult::default();
+        for r in futures::future::join_all(futures).await {
+            result.ok.extend(r.ok);
+            result.failed.extend(r.failed);
+            result.expired.extend(r.expired);
+        }
+        result
+    }
+
+    async fn beat_single(&self, agent: &ApiAgent, game_id: String) -> BeatResult {
+        let mut result = BeatResult::default();
+        let start = std::time::Instant::now();
+        let req = HeartBeatRequest {
+            game_id: game_id.clone(),
+        };
+        let res = call::<_, HeartBeatData>(agent, ApiAgent::HEARTBEAT_URL, &req).await;
+        metrics::heartbeat_rtt("single", start);
+        match res {
+            Ok(res) if res.code == CODE_OK => result.ok.push(game_id),
+            Ok(res) if res.code == CODE_HEARTBEAT_EXPIRED => {
+                result.expired.push((game_id, res.message));
+            }
+            Ok(res) => {
+                let reason = format!("{} {}", res.code, res.message);
+                self.failed(vec![game_id.clone()], reason).await;
+                result.failed.push(game_id);
+            }
+            Err(e) => {
+                self.failed(vec![game_id.clone()], e.to_string()).await;
+                result.failed.push(game_id);
+            }
+        }
+        self.succeeded(&result).await;
+        result
+    }
+
+    async fn beat_batch(&self, agent: &ApiAgent, game_ids: Vec<String>) -> BeatResult {
+        let mut result = BeatResult::default();
+        let start = std::time::Instant::now();
+        let req = BatchHeartBeatRequest {
+            game_ids: game_ids.clone(),
+        };
+        let res = call::<_, BatchHeartBeatData>(agent, ApiAgent::BATCHHEARTBEAT_URL, &req).await;
+        metrics::heartbeat_rtt("batch", start);
+        match res {
+            Ok(res) if res.code == CODE_OK => {
+                let failed_ids = res.data.map(|d| d.failed_game_ids).unwrap_or_default();
+                for id in game_ids {
+                    if failed_ids.contains(&id) {
+                        result
+                            .expired
+                            .push((id, "batch heartbeat failed".to_string()));
+                    } else {
+                        result.ok.push(id);
+                    }
+                }
+            }
+            Ok(res) => {
+                let reason = format!("{} {}", res.code, res.message);
+                self.failed(game_ids.clone(), reason).await;
+                result.failed = game_ids;
+            }
+            Err(e) => {
+                self.failed(game_ids.clone(), e.to_string()).await;
+                result.failed = game_ids;
+            }
+        }
+        self.succeeded(&result).await;
+        result
+    }
+
+    async fn succeeded(&self, result: &BeatResult) {
+        if !result.ok.is_empty() {
+            let game_ids = result.ok.clone();
+            emit(
+                &self.event_handles,
+                ApiServiceEvent::HeartbeatOk { game_ids },
+            )
+            .await;
+        }
+    }
+
+    async fn failed(&self, game_ids: Vec<String>, reason: String) {
+        warn!(?game_ids, %reason, "heartbeat failed");
+        emit(
+            &self.event_handles,
+            ApiServiceEvent::HeartbeatFailed { game_ids, reason },
+        )
+        .await;
+    }
+
+    /// 移除过期场次，返回需要使用身份码重新开启的场次
+    async fn expire(&self, game_id: String, reason: String) -> Option<GameRoom> {
+        let room = self.game_rooms.remove(&game_id).await?;
+        warn!(app_id = room.app_id, %game_id, %reason, "project expired");
+        emit(
+            &self.event_handles,
+            ApiServiceEvent::ProjectExpired {
+                room: room.clone(),
+                reason,
+            },
+        )
+        .await;
+        (self.auto_recover && room.project.is_alive()).then_some(room)
+    }
+
+    async fn recover(self: Arc<Self>, room: GameRoom) {
+        let Some(api_agent) = self.agents.get(&room.access_key) else {
+            let reason = format!("unknown credential {}", room.access_key);
+            emit(
+                &self.event_handles,
+                ApiServiceEvent::ProjectRecoverFailed { room, reason },
+            )
+            .await;
+            return;
+        };
+        let data = match start_session(&api_agent, room.code.expose(), room.app_id).await {
+            Ok(data) => data,
+            Err(e) => {
+                let reason = e.to_string();
+                warn!(app_id = room.app_id, game_id = %room.game_id, %reason, "project recover failed");
+                emit(
+                    &self.event_handles,
+                    ApiServiceEvent::ProjectRecoverFailed { room, reason },
+                )
+                .await;
+                return;
+            }
+        };
+        let new_room = GameRoom {
+            game_id: data.game_info.game_id.clone(),
+            ..room.clone()
+        };
+        self.game_rooms.insert(new_room.clone()).await;
+        self.notify.notify_one();
+        let params = agent_params(&data, room.code.expose(), room.app_id);
+        if !room.project.recover(&data, params).await {
+            // 项目已被释放，结束新场次
+            self.game_rooms.remove(&new_room.game_id).await;
+            let _ = api_agent.end(new_room.app_id, new_room.game_id).await;
+            return;
+        }
+        info!(
+            app_id = room.app_id,
+            old_game_id = %room.game_id,
+            game_id = %new_room.game_id,
+            "project recovered"
+        );
+        emit(
+            &self.event_handles,
+            ApiServiceEvent::ProjectRecovered {
+                old_game_id: room.game_id,
+                room: new_room,
+            },
+        )
+        .await;
+    }
+}
+
+/// 按心跳重试策略发送请求，超过HEARTBEAT_TIMEOUT视为失败
+async fn call<Req, Resp>(
+    agent: &ApiAgent,
+    path: &str,
+    req: &Req,
+) -> Result<ApiResponse<Resp>, ServiceError>
+where
+    Req: serde::Serialize,
+    Resp: serde::de::DeserializeOwned,
+{
+    // 沿用凭证的重试策略，限制重试总耗时不超过心跳时限
+    let policy = agent.retry_policy().within(HEARTBEAT_TIMEOUT);
+    tokio::time::timeout(HEARTBEAT_TIMEOUT, agent.call_with(path, req, &policy))
+        .await
+        .unwrap_or(Err(ServiceError::Timeout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_delay() {
+        let config = HeartbeatConfig::default();
+        for _ in 0..100 {
+            let d = config.next_delay();
+            assert!(d >= Duration::from_secs(18) && d <= Duration::from_secs(22));
+        }
+        let config = HeartbeatConfig {
+            interval: Duration::from_secs(120),
+            ..Default::default()
+        };
+        assert_eq!(config.next_delay(), MAX_HEARTBEAT_INTERVAL);
+        assert!(MAX_HEARTBEAT_INTERVAL + HEARTBEAT_TIMEOUT < SESSION_TIMEOUT);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let ids = (0..450).map(|i| i.to_string()).collect::<Vec<String>>();
+        let chunks = HeartbeatConfig::default().chunks(ids);
+        assert_eq!(
+            chunks.iter().map(|c| c.len()).collect::<Vec<usize>>(),
+            vec![200, 200, 50]
+        );
+        let config = HeartbeatConfig {
+            batch_size: 0,
+            ..Default::default()
+        };
+        assert_eq!(config.chunks(vec!["a".to_string()]).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_call_agent_policy() {
+        use crate::{agent::test_server, auth::Auth, retry::RetryPolicy};
+
+        let body = |code: u32| format!(r#"{{"code":{},"message":"","data":{{}}}}"#, code);
+        let (base_url, server) = test_server(vec![(None, body(6000)), (None, body(CODE_OK))]);
+        // 凭证上配置的可重试返回码同样用于心跳
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(10),
+            retryable_codes: vec![6000],
+            ..Default::default()
+        };
+        let agent = ApiAgent::new(Auth::new("key", "secret").unwrap())
+            .with_base_url(base_url)
+            .with_retry_policy(policy);
+        let req = HeartBeatRequest {
+            game_id: "game".to_string(),
+        };
+        let res = call::<_, HeartBeatData>(&agent, ApiAgent::HEARTBEAT_URL, &req)
+            .await
+            .unwrap();
+        assert_eq!(res.code, CODE_OK);
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::GameRoom;
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum JournalError {
+    #[error("Journal io error")]
+    Io(#[from] io::Error),
+    #[error("Journal format error")]
+    Format(#[from] serde_json::Error),
+}
+
+/// 活跃场次的本地记录（JSON）
+///
+/// 进程崩溃后可读取记录，结束遗留场次或继续发送心跳
+///
+/// 记录中包含明文身份码，unix下文件权限为0600，仅所有者可读写
+#[derive(Debug, Clone)]
+pub struct SessionJournal {
+    path: PathBuf,
+}
+
+impl SessionJournal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// 读取记录，文件不存在时返回空
+    pub fn load(&self) -> Result<Vec<GameRoom>, JournalError> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) if content.trim().is_empty() => Ok(Vec::new()),
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 写入记录，先写临时文件再替换，避免写入中途崩溃损坏记录
+    pub fn save<'a>(&self, rooms: impl Iterator<Item = &'a GameRoom>) -> Result<(), JournalError> {
+        let rooms = rooms.collect::<Vec<&GameRoom>>();
+        let content = serde_json::to_vec_pretty(&rooms)?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut file = fs::File::create(&tmp)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        }
+        file.write_all(&content)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SessionJournal;
+    use crate::GameRoom;
+
+    #[test]
+    fn test_save_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal.json");
+        let journal = SessionJournal::new(&path);
+        assert!(journal.load().unwrap().is_empty());
+        let room = GameRoom {
+            app_id: 1,
+            game_id: "game".to_string(),
+            code: "ABCDEF".into(),
+            access_key: String::new(),
+            project: Default::default(),
+        };
+        // 身份码不出现在Debug输出中，仅以明文写入本地记录
+        assert!(!format!("{:?}", room).contains("ABCDEF"));
+        journal.save([room].iter()).unwrap();
+        assert!(std::fs::read_to_string(&path).unwrap().contains("ABCDEF"));
+        let rooms = journal.load().unwrap();
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].game_id, "game");
+        assert_eq!(rooms[0].code.expose(), "ABCDEF");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+}
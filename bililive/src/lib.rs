@@ -1,165 +1,404 @@
-use agent::ApiAgent;
-use apiv2::V2apis;
+use agent::{AgentPool, ApiAgent};
 use auth::Auth;
-use bililivecmd::{CmdAgent, CmdAgentParams};
+use bililivecmd::CmdAgent;
+use config::Config;
+use error::{ConfigError, ServiceError};
+use event::{emit, ApiServiceEvent, ApiServiceEventHandle, ChannelEventHandle};
+use heartbeat::{HeartbeatConfig, HeartbeatContext};
+use journal::{JournalError, SessionJournal};
+use project::{agent_params, end_room, start_session, Project, ProjectLink, StartPolicy};
+use rooms::GameRooms;
+use serde::{Deserialize, Serialize};
+use shutdown::{end_rooms, RoomEndResult, DEFAULT_SHUTDOWN_DEADLINE};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::thread::{self};
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc::UnboundedReceiver, Notify, RwLock};
+use tokio::task::JoinHandle;
 use tokio::time::Duration;
 
 pub mod agent;
 pub mod apiv2;
 pub mod auth;
+pub mod config;
 pub mod error;
+pub mod event;
+pub mod heartbeat;
+pub mod journal;
+pub mod metrics;
+pub mod project;
+pub mod retry;
+mod rooms;
+pub mod shutdown;
+
+pub use bililivecmd::Secret;
 
 pub struct ApiService {
-    api_agnet: Arc<ApiAgent>,
-    game_rooms: Arc<Mutex<HashMap<String, GameRoom>>>,
+    agents: Arc<AgentPool>,
+    quotas: std::sync::RwLock<HashMap<i64, usize>>,
+    game_rooms: Arc<GameRooms>,
+    heartbeat_task: Option<JoinHandle<()>>,
+    auto_recover: bool,
+    start_policy: StartPolicy,
+    heartbeat_config: HeartbeatConfig,
+    heartbeat_notify: Arc<Notify>,
+    pub event_handles: Arc<RwLock<Vec<Arc<dyn ApiServiceEventHandle>>>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameRoom {
-    app_id: i64,
-    game_id: String,
+    pub app_id: i64,
+    pub game_id: String,
+    /// 开启场次使用的身份码，仅在本地记录中明文保存
+    pub code: Secret<String>,
+    /// 开启场次使用的AccessKey，为空时使用默认凭证
+    #[serde(default)]
+    pub access_key: String,
+    #[serde(skip)]
+    pub(crate) project: ProjectLink,
 }
 
 impl ApiService {
     pub fn new(auth: Auth) -> Self {
+        Self::from_agent(ApiAgent::new(auth))
+    }
+
+    /// 使用配置建立服务，应用凭证、重试策略、心跳及场次恢复配置
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let api_agent =
+            ApiAgent::new(config.auth()?).with_retry_policy(config.retry_policy.clone());
+        Ok(Self::from_agent(api_agent)
+            .with_auto_recover(config.auto_recover)
+            .with_start_policy(config.start_policy)
+            .with_heartbeat_config(config.heartbeat.clone()))
+    }
+
+    /// 使用自定义的ApiAgent（如重试策略）建立服务
+    pub fn from_agent(api_agent: ApiAgent) -> Self {
         Self {
-            game_rooms: Arc::new(Mutex::new(HashMap::new())),
-            api_agnet: Arc::new(ApiAgent::new(auth)),
+            game_rooms: Arc::new(GameRooms::default()),
+            agents: Arc::new(AgentPool::new(api_agent)),
+            quotas: std::sync::RwLock::new(HashMap::new()),
+            heartbeat_task: None,
+            auto_recover: false,
+            start_policy: StartPolicy::default(),
+            heartbeat_config: HeartbeatConfig::default(),
+            heartbeat_notify: Arc::new(Notify::new()),
+            event_handles: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// 添加一组凭证，用于new_project_with
+    pub fn add_credential(&self, auth: Auth) {
+        self.agents.insert(ApiAgent::new(auth));
+    }
+
+    /// 添加使用自定义配置的凭证
+    pub fn add_agent(&self, api_agent: ApiAgent) {
+        self.agents.insert(api_agent);
+    }
+
+    /// 已添加凭证的AccessKey
+    pub fn credentials(&self) -> Vec<String> {
+        self.agents.keys()
+    }
+
+    /// 各凭证测得的本地与服务端时间偏差（秒）
+    pub fn clock_offsets(&self) -> HashMap<String, i64> {
+        self.agents
+            .keys()
+            .into_iter()
+            .filter_map(|k| self.agents.get(&k).map(|a| (k, a.clock_offset())))
+            .collect()
+    }
+
+    /// 设置应用的场次数上限
+    pub fn set_app_quota(&self, app_id: i64, limit: usize) {
+        self.quotas
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(app_id, limit);
+    }
+
+    /// 当前所有活跃场次
+    pub async fn rooms(&self) -> Vec<GameRoom> {
+        self.game_rooms.snapshot().await.into_values().collect()
+    }
+
+    /// 应用的活跃场次
+    pub async fn rooms_by_app(&self, app_id: i64) -> Vec<GameRoom> {
+        let rooms = self.game_rooms.snapshot().await;
+        rooms.into_values().filter(|r| r.app_id == app_id).collect()
+    }
+
+    /// 各应用的场次数及上限
+    pub async fn app_usages(&self) -> Vec<AppUsage> {
+        let mut usages: HashMap<i64, AppUsage> = HashMap::new();
+        let quotas = self
+            .quotas
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        for (app_id, limit) in quotas {
+            usages.insert(app_id, AppUsage::new(app_id, Some(limit)));
+        }
+        for room in self.game_rooms.snapshot().await.into_values() {
+            let usage = usages
+                .entry(room.app_id)
+                .or_insert_with(|| AppUsage::new(room.app_id, None));
+            usage.rooms += 1;
         }
+        usages.into_values().collect()
+    }
+
+    /// 心跳发现场次过期时，是否使用身份码自动重新开启并重新认证长连接
+    pub fn with_auto_recover(mut self, auto_recover: bool) -> Self {
+        self.auto_recover = auto_recover;
+        self
+    }
+
+    /// 同一应用及身份码已有活跃场次时的处理方式
+    pub fn with_start_policy(mut self, start_policy: StartPolicy) -> Self {
+        self.start_policy = start_policy;
+        self
+    }
+
+    /// 使用本地记录保存活跃场次，读取上次运行遗留的场次
+    ///
+    /// 遗留场次需通过end_stale_sessions或resume_sessions处理
+    pub fn with_journal(mut self, journal: SessionJournal) -> Result<Self, JournalError> {
+        self.game_rooms = Arc::new(GameRooms::with_journal(journal)?);
+        Ok(self)
+    }
+
+    /// 结束上次运行遗留的场次
+    pub async fn end_stale_sessions(&mut self) -> Vec<RoomEndResult> {
+        let stale = GameRooms::default();
+        for room in self.game_rooms.take_stale().await {
+            stale.insert(room).await;
+        }
+        end_rooms(
+            &self.agents,
+            &stale,
+            &self.event_handles,
+            DEFAULT_SHUTDOWN_DEADLINE,
+        )
+        .await
+    }
+
+    /// 继续为上次运行遗留的场次发送心跳
+    pub async fn resume_sessions(&mut self) -> Vec<GameRoom> {
+        let rooms = self.game_rooms.resume_stale().await;
+        if !rooms.is_empty() {
+            self.heartbeat_notify.notify_one();
+        }
+        rooms
+    }
+
+    /// 订阅服务事件，事件转发到无界通道，不会阻塞心跳任务
+    pub async fn subscribe(&self) -> UnboundedReceiver<ApiServiceEvent> {
+        let (handle, receiver) = ChannelEventHandle::new();
+        self.event_handles.write().await.push(Arc::new(handle));
+        receiver
+    }
+
+    /// 设置心跳间隔、抖动及批量大小
+    pub fn with_heartbeat_config(mut self, heartbeat_config: HeartbeatConfig) -> Self {
+        self.heartbeat_config = heartbeat_config;
+        self
     }
 
     /// 开启服务
     pub async fn service_start(&mut self) {
-        let game_rooms = Arc::clone(&self.game_rooms);
-        let api_agent = Arc::clone(&self.api_agnet);
-        let future = async move {
-            loop {
-                let rooms_clone = game_rooms.lock().await.clone();
-                let room_len = rooms_clone.len();
-                match room_len {
-                    1 => {
-                        if let Some(keys) = rooms_clone.keys().next() {
-                            let hr = api_agent.heartbeat(keys.clone()).await;
-                            if let Ok(res) = hr {
-                                println!("heartbeat\n room:{:?}\n rescode:{}", keys, res.code);
-                            }
-                        }
-                    }
-                    2.. => {
-                        let keys = rooms_clone.keys().cloned().collect::<Vec<String>>();
-                        let hr = api_agent.batch_heartbeat(keys).await;
-                        if let Ok(res) = hr {
-                            println!(
-                                "batch_heartbeat\n rooms:{:?}\n rescode:{}",
-                                rooms_clone, res.code
-                            );
-                        }
-                    }
-                    _ => {
-                        println!("no rooms");
-                    }
-                }
-                tokio::time::sleep(Duration::from_secs(20)).await;
-            }
-        };
-        tokio::spawn(future);
-    }
-
-    pub async fn new_project(&mut self, code: String, app_id: i64) -> Option<CmdAgent> {
-        let res = self.api_agnet.start(code.clone(), app_id).await;
-        if let Ok(resp) = res {
-            if let Some(data) = resp.data {
-                let game_id = data.game_info.game_id;
-                if !game_id.is_empty() {
-                    self.game_rooms
-                        .lock()
-                        .await
-                        .insert(game_id.clone(), GameRoom { app_id, game_id });
+        let future = Arc::new(HeartbeatContext {
+            agents: Arc::clone(&self.agents),
+            game_rooms: Arc::clone(&self.game_rooms),
+            event_handles: Arc::clone(&self.event_handles),
+            auto_recover: self.auto_recover,
+            config: self.heartbeat_config.clone(),
+            notify: Arc::clone(&self.heartbeat_notify),
+        })
+        .run();
+        if let Some(task) = self.heartbeat_task.replace(tokio::spawn(future)) {
+            task.abort();
+        }
+    }
+
+    /// 使用身份码开启项目（默认凭证）
+    pub async fn new_project(&self, code: String, app_id: i64) -> Result<Project, ServiceError> {
+        let access_key = self.agents.default_key().to_string();
+        self.new_project_with(&access_key, code, app_id).await
+    }
+
+    /// 使用指定凭证及身份码开启项目
+    ///
+    /// 可并发调用，应用的场次名额在请求开启前预留，开启失败时释放
+    pub async fn new_project_with(
+        &self,
+        access_key: &str,
+        code: String,
+        app_id: i64,
+    ) -> Result<Project, ServiceError> {
+        let api_agent = self
+            .agents
+            .get(access_key)
+            .ok_or_else(|| ServiceError::UnknownCredential(access_key.to_string()))?;
+        let found = self
+            .game_rooms
+            .find(app_id, api_agent.access_key(), &code)
+            .await;
+        if let Some(room) = found {
+            match (self.start_policy, room.project.upgrade()) {
+                (StartPolicy::Reuse, Some(project)) => return Ok(project),
+                (_, Some(project)) => {
+                    // 结束失败时由重新开启的结果反映场次状态
+                    let _ = project.stop().await;
                 }
-                println!("{:?}", data.websocket_info);
-                return Some(CmdAgent::new(CmdAgentParams {
-                    auth_body: data.websocket_info.auth_body,
-                    server_url: data.websocket_info.wss_link.first().unwrap().clone(),
-                    app_id,
-                    user_code: code,
-                }));
+                // 没有项目句柄（如从本地记录恢复）的场次无法复用，结束后重新开启
+                (_, None) => self.stop_project(room.game_id).await,
             }
         }
-        None
+        let limit = self
+            .quotas
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&app_id)
+            .copied();
+        if !self.game_rooms.reserve(app_id, limit).await {
+            let limit = limit.unwrap_or_default();
+            return Err(ServiceError::QuotaExceeded { app_id, limit });
+        }
+        let data = match start_session(&api_agent, &code, app_id).await {
+            Ok(data) => data,
+            Err(e) => {
+                self.game_rooms.cancel_reservation(app_id).await;
+                return Err(e);
+            }
+        };
+        let game_id = data.game_info.game_id.clone();
+        tracing::info!(
+            app_id,
+            %game_id,
+            room_id = data.anchor_info.room_id,
+            "project started"
+        );
+        let agent = CmdAgent::new(agent_params(&data, &code, app_id));
+        let project = Project::new(
+            app_id,
+            game_id.clone(),
+            data.anchor_info,
+            agent,
+            Arc::clone(&api_agent),
+            Arc::clone(&self.game_rooms),
+            Arc::clone(&self.event_handles),
+        );
+        let room = GameRoom {
+            app_id,
+            game_id,
+            code: code.into(),
+            access_key: api_agent.access_key().to_string(),
+            project: project.link(),
+        };
+        self.game_rooms.insert_reserved(room.clone()).await;
+        emit(
+            &self.event_handles,
+            ApiServiceEvent::ProjectStarted {
+                room,
+                anchor_info: project.anchor_info(),
+            },
+        )
+        .await;
+        // 唤醒心跳任务，立即发送首次心跳
+        self.heartbeat_notify.notify_one();
+        Ok(project)
     }
 
-    pub async fn stop_project(&mut self, game_id: String) {
-        if let Some(game_room) = self.game_rooms.lock().await.remove(&game_id) {
-            let res = self
-                .api_agnet
-                .end(game_room.app_id, game_room.game_id.clone())
-                .await;
-            if let Ok(resp) = res {
-                println!("{:?} {:?}", game_room, resp);
+    /// 结束场次
+    pub async fn stop_project(&self, game_id: String) {
+        let room = self.game_rooms.remove(&game_id).await;
+        if let Some(room) = room {
+            if let Some(api_agent) = self.agents.get(&room.access_key) {
+                let _ = end_room(&api_agent, &self.event_handles, room).await;
             }
         }
     }
 
-    pub async fn stop_all_projects(&mut self) {
-        stop_all_projects(self);
+    /// 结束所有场次
+    pub async fn stop_all_projects(&mut self) -> Vec<RoomEndResult> {
+        end_rooms(
+            &self.agents,
+            &self.game_rooms,
+            &self.event_handles,
+            DEFAULT_SHUTDOWN_DEADLINE,
+        )
+        .await
     }
-}
 
-impl Drop for ApiService {
-    fn drop(&mut self) {
-        println!("droping...");
-        stop_all_projects(self);
-        println!("droped");
+    /// 停止心跳并并发结束所有场次
+    pub async fn shutdown(self) -> Vec<RoomEndResult> {
+        self.shutdown_with_deadline(DEFAULT_SHUTDOWN_DEADLINE).await
     }
-}
 
-fn stop_all_projects(service: &mut ApiService) {
-    let agent = Arc::clone(&service.api_agnet);
-    let rooms = Arc::clone(&service.game_rooms);
-    thread::spawn(move || {
-        tokio::runtime::Runtime::new().unwrap().block_on(async {
-            let binding = rooms.lock().await;
-            let keys = binding.keys().clone();
-            for k in keys {
-                if let Some(game_room) = rooms.lock().await.remove(k) {
-                    let _ = agent.end(game_room.app_id, game_room.game_id).await;
-                }
-            }
-        });
-    });
-}
+    /// 停止心跳，关闭所有长连接并并发结束场次，每个场次最多等待deadline
+    pub async fn shutdown_with_deadline(mut self, deadline: Duration) -> Vec<RoomEndResult> {
+        if let Some(task) = self.heartbeat_task.take() {
+            task.abort();
+        }
+        end_rooms(
+            &self.agents,
+            &self.game_rooms,
+            &self.event_handles,
+            deadline,
+        )
+        .await
+    }
 
-#[allow(unused)]
-fn env_access_key() -> String {
-    dotenvy::var("ACCESS_KEY").unwrap()
+    /// 等待Ctrl-C或SIGTERM信号后关闭服务
+    pub async fn shutdown_on_signal(self) -> Vec<RoomEndResult> {
+        shutdown::wait_for_signal().await;
+        self.shutdown().await
+    }
 }
 
-#[allow(unused)]
-fn env_access_secret() -> String {
-    dotenvy::var("ACCESS_SECRET").unwrap()
+impl Drop for ApiService {
+    fn drop(&mut self) {
+        // 场次需通过shutdown结束，这里只停止心跳
+        if let Some(task) = self.heartbeat_task.take() {
+            task.abort();
+        }
+    }
 }
 
-#[allow(unused)]
-fn env_live_code() -> String {
-    dotenvy::var("LIVE_CODE").unwrap()
+/// 应用的场次使用情况
+#[derive(Debug, Clone)]
+pub struct AppUsage {
+    pub app_id: i64,
+    /// 活跃场次数
+    pub rooms: usize,
+    /// 场次数上限
+    pub quota: Option<usize>,
 }
 
-#[allow(unused)]
-fn env_app_id() -> i64 {
-    dotenvy::var("APP_ID").unwrap().parse::<i64>().unwrap()
+impl AppUsage {
+    fn new(app_id: i64, quota: Option<usize>) -> Self {
+        Self {
+            app_id,
+            rooms: 0,
+            quota,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        auth::Auth, env_access_key, env_access_secret, env_app_id, env_live_code, ApiService,
+        agent::{test_server, test_start_body, ApiAgent},
+        auth::Auth,
+        config::Config,
+        error::ServiceError,
+        project::test_project,
+        ApiService, GameRoom,
     };
     use std::sync::Arc;
-    use tokio::time::Duration;
 
     #[tokio::test]
     async fn it_works() {
@@ -172,19 +411,105 @@ mod tests {
         println!("{:?}", body);
     }
 
+    #[tokio::test]
+    async fn test_app_quota() {
+        let service = ApiService::new(Auth::new("key1", "secret1").unwrap());
+        service.add_credential(Auth::new("key2", "secret2").unwrap());
+        let mut credentials = service.credentials();
+        credentials.sort();
+        assert_eq!(credentials, ["key1", "key2"]);
+        service.set_app_quota(1, 0);
+        let r = service
+            .new_project_with("key2", "code".to_string(), 1)
+            .await;
+        assert!(matches!(
+            r,
+            Err(ServiceError::QuotaExceeded {
+                app_id: 1,
+                limit: 0
+            })
+        ));
+        let r = service
+            .new_project_with("key3", "code".to_string(), 1)
+            .await;
+        assert!(matches!(r, Err(ServiceError::UnknownCredential(_))));
+        let usages = service.app_usages().await;
+        assert_eq!(usages.len(), 1);
+        assert_eq!((usages[0].rooms, usages[0].quota), (0, Some(0)));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_quota() {
+        let start = |game_id: &str| (None, test_start_body(game_id));
+        let (base_url, server) = test_server(vec![start("game0"), start("game1")]);
+        let api_agent = ApiAgent::new(Auth::new("key", "secret").unwrap()).with_base_url(base_url);
+        let service = ApiService::from_agent(api_agent);
+        service.set_app_quota(1, 2);
+        let starts = (0..4).map(|i| service.new_project(format!("code{}", i), 1));
+        let results = futures::future::join_all(starts).await;
+        let started = results.iter().filter(|r| r.is_ok()).count();
+        let exceeded = results
+            .iter()
+            .filter(|r| {
+                matches!(
+                    r,
+                    Err(ServiceError::QuotaExceeded {
+                        app_id: 1,
+                        limit: 2
+                    })
+                )
+            })
+            .count();
+        assert_eq!((started, exceeded), (2, 2));
+        assert_eq!(server.join().unwrap().len(), 2);
+        assert_eq!(service.rooms_by_app(1).await.len(), 2);
+
+        // 开启失败时释放预留的名额
+        let rejected = r#"{"code":7007,"message":"invalid code","data":null}"#.to_string();
+        let (base_url, server) = test_server(vec![(None, rejected), start("game")]);
+        let api_agent = ApiAgent::new(Auth::new("key", "secret").unwrap()).with_base_url(base_url);
+        let service = ApiService::from_agent(api_agent);
+        service.set_app_quota(1, 1);
+        assert!(service.new_project("code".to_string(), 1).await.is_err());
+        assert!(service.new_project("code".to_string(), 1).await.is_ok());
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_reuse_project() {
+        let service = ApiService::new(Auth::new("key", "secret").unwrap());
+        let project = test_project(&service, "game");
+        let room = GameRoom {
+            app_id: 1,
+            game_id: "game".to_string(),
+            code: "code".into(),
+            access_key: "key".to_string(),
+            project: project.link(),
+        };
+        service.game_rooms.insert(room).await;
+        let reused = service.new_project("code".to_string(), 1).await.unwrap();
+        assert_eq!(reused.game_id(), "game");
+        drop(project);
+        // 仍有句柄时不会结束场次
+        assert_eq!(service.rooms().await.len(), 1);
+        service.game_rooms.drain().await;
+    }
+
     #[tokio::test]
     async fn test_service_start() {
         use bililivecmd::test_handle::TestHandler;
-        // 使用AccessKey和Secret建立服务
-        let mut service = ApiService::new(Auth::new(env_access_key(), env_access_secret()));
+        // 从环境变量或.env文件加载配置，使用其中的AccessKey和Secret建立服务
+        let config = Config::from_dotenv().unwrap();
+        let mut service = ApiService::from_config(&config).unwrap();
         // 使用直播code和app_id开启项目，在启动服务后会自动按频率发送心跳
         // 认证成功同时还会创建一个长连接代理（可独立工作）
-        let mut agent = service
-            .new_project(env_live_code(), env_app_id())
+        let project = service
+            .new_project(config.code().unwrap().to_string(), config.app_id)
             .await
             .unwrap();
+        let agent = project.agent();
         // 为长连接代理添加处理对象 （可选择性 是否需要处理层 或 多个处理层） raw -> proto -> cmd
-        let handle = Arc::new(TestHandler::default());
+        let handle = Arc::new(TestHandler);
         // // 处理原始字符串
         // let raw = Arc::clone(&handle);
         // //
@@ -197,38 +522,39 @@ mod tests {
         agent.cmd_handles.write().await.push(cmd);
         // 启动长连接代理
         agent.start().await;
-        // 启动服务（用于自动发送项目心跳）,正常退出时会自动调用end api
+        // 启动服务（用于自动发送项目心跳）
         service.service_start().await;
-
-        //主进程保持运行
-        loop {
-            tokio::time::sleep(Duration::from_secs(1)).await;
+        // 收到Ctrl-C或SIGTERM后结束所有场次
+        for r in service.shutdown_on_signal().await {
+            println!("{:?}", r);
         }
     }
 
     #[tokio::test]
     async fn test_sqlite_handle() {
         use bililivecmd_sqlite_handle::SqliteHandler;
-        let mut service = ApiService::new(Auth::new(env_access_key(), env_access_secret()));
-        let mut agent = service
-            .new_project(env_live_code(), env_app_id())
+        let config = Config::from_dotenv().unwrap();
+        let mut service = ApiService::from_config(&config).unwrap();
+        let project = service
+            .new_project(config.code().unwrap().to_string(), config.app_id)
             .await
             .unwrap();
+        let agent = project.agent();
         // 为长连接代理添加处理对象 （可选择性 是否需要处理层 或 多个处理层） raw -> proto -> cmd
         // 使用sqlite handle 存储弹幕消息
-        let mut sqlite = SqliteHandler::new(None).await;
+        let database_url = config.database_url().unwrap().to_string();
+        let mut sqlite = SqliteHandler::new(Some(database_url)).await.unwrap();
         sqlite.console_saved = true;
         let handle = Arc::new(sqlite);
         let cmd = Arc::clone(&handle);
         agent.cmd_handles.write().await.push(cmd);
         // 启动长连接代理
         agent.start().await;
-        // 启动服务（用于自动发送项目心跳）,正常退出时会自动调用end api
+        // 启动服务（用于自动发送项目心跳）
         service.service_start().await;
-
-        //主进程保持运行
-        loop {
-            tokio::time::sleep(Duration::from_secs(1)).await;
+        // 场次需通过shutdown结束，收到Ctrl-C或SIGTERM后调用end api结束所有场次
+        for r in service.shutdown_on_signal().await {
+            println!("{:?}", r);
         }
     }
 }
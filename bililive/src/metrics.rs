@@ -0,0 +1,165 @@
+//! 服务指标，启用metrics特性后注册到prometheus默认Registry
+//!
+//! 长连接指标见bililivecmd::metrics，未启用时所有记录函数为空操作
+
+use crate::error::ServiceError;
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+mod imp {
+    use prometheus::{
+        register_histogram_vec, register_int_counter_vec, Encoder, HistogramVec, IntCounterVec,
+        TextEncoder,
+    };
+    use std::{net::SocketAddr, sync::LazyLock, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// API请求耗时，按接口路径分类
+    pub static API_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+        register_histogram_vec!(
+            "bililive_api_latency_seconds",
+            "Open platform API latency in seconds",
+            &["path"]
+        )
+        .unwrap()
+    });
+
+    /// API错误，按接口路径及错误码分类（请求失败为transport，解析失败为decode，未知错误码为other）
+    pub static API_ERRORS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+        register_int_counter_vec!(
+            "bililive_api_errors_total",
+            "Open platform API errors by code",
+            &["path", "code"]
+        )
+        .unwrap()
+    });
+
+    /// 项目心跳往返耗时，按single/batch分类
+    pub static HEARTBEAT_RTT: LazyLock<HistogramVec> = LazyLock::new(|| {
+        register_histogram_vec!(
+            "bililive_heartbeat_rtt_seconds",
+            "Project heartbeat round trip time in seconds",
+            &["kind"]
+        )
+        .unwrap()
+    });
+
+    /// 以Prometheus文本格式输出默认Registry中的所有指标
+    pub fn gather() -> String {
+        let mut buffer = Vec::new();
+        let encoder = TextEncoder::new();
+        let _ = encoder.encode(&prometheus::gather(), &mut buffer);
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+
+    /// 启动指标抓取服务，任意路径均返回gather()的内容
+    ///
+    /// 仅绑定地址失败时返回错误，accept失败记录日志后继续
+    pub async fn serve(addr: SocketAddr) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        loop {
+            let mut stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    // 如文件描述符耗尽，稍后重试避免空转
+                    tracing::warn!(error = %e, "metrics accept failed");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf).await;
+                let body = gather();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    prometheus::TEXT_FORMAT,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use imp::*;
+
+pub(crate) fn api_call<T>(
+    path: &str,
+    start: Instant,
+    result: &Result<crate::apiv2::ApiResponse<T>, ServiceError>,
+) {
+    #[cfg(feature = "metrics")]
+    {
+        API_LATENCY
+            .with_label_values(&[path])
+            .observe(start.elapsed().as_secs_f64());
+        let code = match result {
+            Ok(resp) if resp.code == crate::apiv2::CODE_OK => None,
+            Ok(resp) => Some(code_label(resp.code)),
+            Err(ServiceError::APIDeserializeError(_)) => Some("decode"),
+            Err(_) => Some("transport"),
+        };
+        if let Some(code) = code {
+            API_ERRORS.with_label_values(&[path, code]).inc();
+        }
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (path, start, result);
+}
+
+/// 错误码标签，只使用已知的返回码，避免服务端数据产生不受控的标签
+#[cfg(feature = "metrics")]
+fn code_label(code: u32) -> &'static str {
+    use crate::apiv2::*;
+    match code {
+        CODE_INVALID_PARAMS => "4000",
+        CODE_INVALID_APP => "4001",
+        CODE_INVALID_SIGN => "4002",
+        CODE_REQUEST_EXPIRED => "4003",
+        CODE_SERVICE_ERROR => "5000",
+        CODE_REQUEST_COOLDOWN => "7001",
+        CODE_HEARTBEAT_EXPIRED => "7003",
+        CODE_INVALID_CODE => "7007",
+        _ => "other",
+    }
+}
+
+pub(crate) fn heartbeat_rtt(kind: &str, start: Instant) {
+    #[cfg(feature = "metrics")]
+    HEARTBEAT_RTT
+        .with_label_values(&[kind])
+        .observe(start.elapsed().as_secs_f64());
+    #[cfg(not(feature = "metrics"))]
+    let _ = (kind, start);
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use crate::apiv2::{ApiResponse, EndData, CODE_INVALID_CODE};
+
+    #[test]
+    fn test_api_metrics() {
+        let resp = ApiResponse::<EndData> {
+            code: CODE_INVALID_CODE,
+            ..Default::default()
+        };
+        api_call("/v2/app/end", Instant::now(), &Ok(resp));
+        heartbeat_rtt("single", Instant::now());
+        assert_eq!(
+            API_ERRORS.with_label_values(&["/v2/app/end", "7007"]).get(),
+            1
+        );
+        assert_eq!(code_label(123456), "other");
+        let text = gather();
+        assert!(text.contains("bililive_api_latency_seconds"));
+        assert!(text.contains("bililive_heartbeat_rtt_seconds"));
+    }
+}
@@ -0,0 +1,276 @@
+use crate::{
+    agent::ApiAgent,
+    apiv2::{AnchorInfo, StartData, V2apis},
+    error::ServiceError,
+    event::{emit, ApiServiceEvent, EventHandles},
+    rooms::GameRooms,
+    GameRoom,
+};
+use bililivecmd::{AnchorContext, CmdAgent, CmdAgentParams, SessionContext};
+use std::sync::{Arc, RwLock, Weak};
+
+/// 已开启的项目
+///
+/// 持有场次信息与长连接代理，stop时同时关闭长连并结束场次；
+/// 未调用stop即被释放时，会在当前tokio运行时中自动结束场次
+///
+/// 可克隆，所有克隆释放后才会自动结束场次
+#[derive(Clone)]
+pub struct Project {
+    inner: Arc<ProjectInner>,
+}
+
+/// 同一身份码重复开启项目时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StartPolicy {
+    /// 返回已有的项目句柄
+    #[default]
+    Reuse,
+    /// 结束已有场次后重新开启
+    Replace,
+}
+
+pub(crate) struct ProjectInner {
+    app_id: i64,
+    session: RwLock<Session>,
+    agent: CmdAgent,
+    api_agent: Arc<ApiAgent>,
+    game_rooms: Arc<GameRooms>,
+    event_handles: EventHandles,
+}
+
+#[derive(Clone)]
+struct Session {
+    game_id: String,
+    anchor_info: AnchorInfo,
+}
+
+/// GameRoom到项目的弱引用，用于场次恢复时更新项目
+#[derive(Clone, Default)]
+pub(crate) struct ProjectLink(Weak<ProjectInner>);
+
+impl std::fmt::Debug for ProjectLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProjectLink")
+    }
+}
+
+impl Project {
+    pub(crate) fn new(
+        app_id: i64,
+        game_id: String,
+        anchor_info: AnchorInfo,
+        agent: CmdAgent,
+        api_agent: Arc<ApiAgent>,
+        game_rooms: Arc<GameRooms>,
+        event_handles: EventHandles,
+    ) -> Self {
+        Self {
+            inner: Arc::new(ProjectInner {
+                app_id,
+                session: RwLock::new(Session {
+                    game_id,
+                    anchor_info,
+                }),
+                agent,
+                api_agent,
+                game_rooms,
+                event_handles,
+            }),
+        }
+    }
+
+    pub(crate) fn link(&self) -> ProjectLink {
+        ProjectLink(Arc::downgrade(&self.inner))
+    }
+
+    pub fn app_id(&self) -> i64 {
+        self.inner.app_id
+    }
+
+    /// 场次id（场次恢复后会更新）
+    pub fn game_id(&self) -> String {
+        self.inner.session.read().unwrap().game_id.clone()
+    }
+
+    /// 主播信息
+    pub fn anchor_info(&self) -> AnchorInfo {
+        self.inner.session.read().unwrap().anchor_info.clone()
+    }
+
+    /// 长连接代理
+    pub fn agent(&self) -> &CmdAgent {
+        &self.inner.agent
+    }
+
+    /// 关闭长连接并结束场次
+    ///
+    /// 场次已被服务结束时只关闭长连接
+    pub async fn stop(&self) -> Result<(), ServiceError> {
+        self.inner.agent.stop().await;
+        let room = self.inner.game_rooms.remove(&self.game_id()).await;
+        if let Some(room) = room {
+            let inner = &self.inner;
+            return end_room(&inner.api_agent, &inner.event_handles, room).await;
+        }
+        Ok(())
+    }
+}
+
+impl ProjectLink {
+    /// 项目句柄是否仍存在
+    pub(crate) fn is_alive(&self) -> bool {
+        self.0.strong_count() > 0
+    }
+
+    pub(crate) fn upgrade(&self) -> Option<Project> {
+        self.0.upgrade().map(|inner| Project { inner })
+    }
+
+    /// 场次重新开启后更新项目信息并重新认证长连接
+    pub(crate) async fn recover(&self, data: &StartData, params: CmdAgentParams) -> bool {
+        let Some(inner) = self.0.upgrade() else {
+            return false;
+        };
+        *inner.session.write().unwrap() = Session {
+            game_id: data.game_info.game_id.clone(),
+            anchor_info: data.anchor_info.clone(),
+        };
+        if inner.agent.is_started() {
+            inner.agent.reconnect(params).await;
+        } else {
+            inner.agent.set_params(params);
+        }
+        true
+    }
+}
+
+impl Drop for ProjectInner {
+    fn drop(&mut self) {
+        self.agent.abort();
+        let game_id = self.session.read().unwrap().game_id.clone();
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!(%game_id, "project dropped outside tokio runtime");
+            return;
+        };
+        let api_agent = Arc::clone(&self.api_agent);
+        let game_rooms = Arc::clone(&self.game_rooms);
+        let event_handles = Arc::clone(&self.event_handles);
+        runtime.spawn(async move {
+            let room = game_rooms.remove(&game_id).await;
+            if let Some(room) = room {
+                let _ = end_room(&api_agent, &event_handles, room).await;
+            }
+        });
+    }
+}
+
+/// 结束场次并发送ProjectEnded事件
+pub(crate) async fn end_room(
+    api_agent: &ApiAgent,
+    event_handles: &EventHandles,
+    room: GameRoom,
+) -> Result<(), ServiceError> {
+    let result = match api_agent.end(room.app_id, room.game_id.clone()).await {
+        Ok(resp) => resp.into_result().map(|_| ()),
+        Err(e) => Err(e),
+    };
+    let error = result.as_ref().err().map(|e| e.to_string());
+    match &error {
+        None => tracing::info!(app_id = room.app_id, game_id = %room.game_id, "project ended"),
+        Some(e) => {
+            tracing::warn!(app_id = room.app_id, game_id = %room.game_id, error = %e, "failed to end project")
+        }
+    }
+    emit(event_handles, ApiServiceEvent::ProjectEnded { room, error }).await;
+    result
+}
+
+/// 使用身份码开启场次并检查返回数据
+pub(crate) async fn start_session(
+    api_agent: &ApiAgent,
+    code: &str,
+    app_id: i64,
+) -> Result<StartData, ServiceError> {
+    let data = api_agent
+        .start(code.to_string(), app_id)
+        .await?
+        .into_result()?
+        .ok_or(ServiceError::MalformedStartData("missing data"))?;
+    if let Err(e) = data.validate() {
+        // 场次已开启但数据不可用，结束场次避免占用
+        if !data.game_info.game_id.is_empty() {
+            let _ = api_agent.end(app_id, data.game_info.game_id).await;
+        }
+        return Err(e);
+    }
+    Ok(data)
+}
+
+/// 由开启数据构建长连接参数
+pub(crate) fn agent_params(data: &StartData, code: &str, app_id: i64) -> CmdAgentParams {
+    CmdAgentParams {
+        auth_body: data.websocket_info.auth_body.clone(),
+        server_url: data.server_url().cloned().unwrap_or_default(),
+        app_id,
+        user_code: code.into(),
+        session: SessionContext {
+            app_id,
+            game_id: data.game_info.game_id.clone(),
+            anchor: AnchorContext {
+                room_id: data.anchor_info.room_id as i64,
+                uid: data.anchor_info.uid as i64,
+                open_id: data.anchor_info.open_id.clone(),
+                union_id: data.anchor_info.union_id.clone(),
+                uname: data.anchor_info.uname.clone(),
+                uface: data.anchor_info.uface.clone(),
+            },
+        },
+    }
+}
+
+/// 使用服务的凭证、场次及事件处理创建测试项目，不请求开启接口
+#[cfg(test)]
+pub(crate) fn test_project(service: &crate::ApiService, game_id: &str) -> Project {
+    Project::new(
+        1,
+        game_id.to_string(),
+        Default::default(),
+        CmdAgent::new(Default::default()),
+        service.agents.get("key").unwrap(),
+        Arc::clone(&service.game_rooms),
+        Arc::clone(&service.event_handles),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{agent_params, test_project};
+    use crate::{apiv2::StartData, auth::Auth, ApiService};
+
+    #[tokio::test]
+    async fn test_stop_ended_project() {
+        let service = ApiService::new(Auth::new("key", "secret").unwrap());
+        let project = test_project(&service, "game");
+        assert_eq!(project.game_id(), "game");
+        assert!(project.stop().await.is_ok());
+        assert!(!project.agent().is_working());
+    }
+
+    #[tokio::test]
+    async fn test_recover_link() {
+        let service = ApiService::new(Auth::new("key", "secret").unwrap());
+        let project = test_project(&service, "old");
+        let link = project.link();
+        let mut data = StartData::default();
+        data.game_info.game_id = "new".to_string();
+        data.websocket_info.wss_link = vec!["wss://link".to_string()];
+        data.anchor_info.open_id = "anchor".to_string();
+        assert!(link.recover(&data, agent_params(&data, "code", 1)).await);
+        assert_eq!(project.game_id(), "new");
+        assert_eq!(project.agent().params().session.game_id, "new");
+        assert_eq!(project.agent().params().session.anchor.open_id, "anchor");
+        drop(project);
+        assert!(!link.recover(&data, agent_params(&data, "code", 1)).await);
+    }
+}
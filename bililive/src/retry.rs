@@ -0,0 +1,210 @@
+use crate::{
+    apiv2::{CODE_REQUEST_COOLDOWN, CODE_SERVICE_ERROR},
+    error::ServiceError,
+};
+use rand::Rng;
+use std::time::Duration;
+
+/// within()缩减尝试次数时保留的单次请求超时下限
+const MIN_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// API请求重试策略
+///
+/// 每次重试都会重新构建请求，生成新的nonce和timestamp签名
+///
+/// 开启场次不是幂等的，请求错误仅在连接失败（请求未到达服务端）时重试，
+/// 可重试的返回码与其它接口相同
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 最大尝试次数（包含首次请求）
+    pub max_attempts: u32,
+    /// 首次重试前的等待时间，之后按指数增长
+    pub base_delay: Duration,
+    /// 单次等待时间上限
+    pub max_delay: Duration,
+    /// 随机抖动比例（0.0 ~ 1.0）
+    pub jitter: f64,
+    /// 可重试的API返回码
+    pub retryable_codes: Vec<u32>,
+    /// 建立连接超时
+    pub connect_timeout: Duration,
+    /// 单次请求超时（包含读取响应）
+    pub request_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+            jitter: 0.2,
+            retryable_codes: vec![CODE_SERVICE_ERROR, CODE_REQUEST_COOLDOWN],
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 不重试，仅请求一次
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// 限制最坏耗时不超过limit的策略，保留返回码及退避设置
+    ///
+    /// 依次减少尝试次数，直到每次请求至少有MIN_REQUEST_TIMEOUT，再缩短单次请求超时
+    pub fn within(&self, limit: Duration) -> Self {
+        let mut policy = self.clone();
+        policy.max_attempts = policy.max_attempts.max(1);
+        while policy.max_attempts > 1
+            && policy.worst_backoff() + MIN_REQUEST_TIMEOUT * policy.max_attempts > limit
+        {
+            policy.max_attempts -= 1;
+        }
+        let budget = limit.saturating_sub(policy.worst_backoff()) / policy.max_attempts;
+        policy.request_timeout = policy.request_timeout.min(budget);
+        policy
+    }
+
+    /// 所有尝试均超时的最长耗时（含退避等待）
+    pub fn worst_case(&self) -> Duration {
+        self.request_timeout.saturating_mul(self.max_attempts) + self.worst_backoff()
+    }
+
+    /// 所有重试的最长退避等待
+    fn worst_backoff(&self) -> Duration {
+        let jitter = 1.0 + self.jitter.clamp(0.0, 1.0);
+        (1..self.max_attempts)
+            .map(|attempt| self.delay(attempt).mul_f64(jitter))
+            .sum()
+    }
+
+    /// 第attempt次请求失败后的等待时间（attempt从1开始）
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.delay(attempt);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        let factor = rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter);
+        delay.mul_f64(factor)
+    }
+
+    /// 未加抖动的等待时间
+    fn delay(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(16);
+        self.base_delay
+            .saturating_mul(1u32 << exp)
+            .min(self.max_delay)
+    }
+
+    /// API返回码是否可重试
+    pub fn is_retryable_code(&self, code: u32) -> bool {
+        self.retryable_codes.contains(&code)
+    }
+
+    /// 请求是否确定未到达服务端（连接失败），非幂等接口仅重试此类错误
+    pub fn is_unsent_error(&self, error: &ServiceError) -> bool {
+        matches!(error, ServiceError::ReqwestError(e) if e.is_connect())
+    }
+
+    /// 请求错误是否可重试（网络错误、超时、限流及服务端5xx）
+    pub fn is_retryable_error(&self, error: &ServiceError) -> bool {
+        match error {
+            ServiceError::ReqwestError(e) => {
+                if let Some(status) = e.status() {
+                    return status.as_u16() == 429 || status.is_server_error();
+                }
+                e.is_timeout() || e.is_connect() || e.is_request()
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apiv2::CODE_INVALID_SIGN;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(3), Duration::from_millis(2000));
+        assert_eq!(policy.backoff(10), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_backoff_jitter() {
+        let policy = RetryPolicy::default();
+        for _ in 0..100 {
+            let d = policy.backoff(2);
+            assert!(d >= Duration::from_millis(800) && d <= Duration::from_millis(1200));
+        }
+    }
+
+    #[test]
+    fn test_worst_case() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.worst_case(), Duration::from_millis(31500));
+    }
+
+    #[test]
+    fn test_within() {
+        let limit = Duration::from_secs(10);
+        let policy = RetryPolicy {
+            retryable_codes: vec![CODE_INVALID_SIGN],
+            ..Default::default()
+        }
+        .within(limit);
+        assert!(policy.worst_case() <= limit);
+        assert_eq!(policy.max_attempts, 3);
+        assert_eq!(policy.retryable_codes, [CODE_INVALID_SIGN]);
+
+        // 尝试次数过多时减少次数
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            ..Default::default()
+        }
+        .within(limit);
+        assert!(policy.worst_case() <= limit);
+        assert!(policy.request_timeout >= MIN_REQUEST_TIMEOUT);
+        assert!(policy.max_attempts < 10);
+
+        // 已在限制内时不变
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            request_timeout: Duration::from_secs(2),
+            ..Default::default()
+        };
+        let within = policy.within(limit);
+        assert_eq!(within.max_attempts, 2);
+        assert_eq!(within.request_timeout, policy.request_timeout);
+
+        let policy = RetryPolicy::none().within(limit);
+        assert_eq!(policy.max_attempts, 1);
+        assert_eq!(policy.request_timeout, limit);
+    }
+
+    #[test]
+    fn test_retryable() {
+        let policy = RetryPolicy::default();
+        assert!(policy.is_retryable_code(CODE_SERVICE_ERROR));
+        assert!(!policy.is_retryable_code(CODE_INVALID_SIGN));
+        assert!(!policy.is_retryable_error(&ServiceError::Unknown));
+        assert_eq!(RetryPolicy::none().max_attempts, 1);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::{
+    journal::{JournalError, SessionJournal},
+    GameRoom,
+};
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+/// 服务管理的场次，变更时写入本地记录
+#[derive(Default)]
+pub(crate) struct GameRooms {
+    state: Mutex<RoomsState>,
+    journal: Option<SessionJournal>,
+}
+
+#[derive(Default)]
+struct RoomsState {
+    rooms: HashMap<String, GameRoom>,
+    /// 本地记录中尚未处理的遗留场次
+    stale: Vec<GameRoom>,
+    /// 各应用开启中的场次预留的名额
+    reserved: HashMap<i64, usize>,
+}
+
+impl RoomsState {
+    fn release(&mut self, app_id: i64) {
+        if let Some(reserved) = self.reserved.get_mut(&app_id) {
+            *reserved -= 1;
+            if *reserved == 0 {
+                self.reserved.remove(&app_id);
+            }
+        }
+    }
+}
+
+impl GameRooms {
+    /// 读取本地记录，遗留场次在处理前会一直保留在记录中
+    pub fn with_journal(journal: SessionJournal) -> Result<Self, JournalError> {
+        let stale = journal.load()?;
+        Ok(Self {
+            state: Mutex::new(RoomsState {
+                rooms: HashMap::new(),
+                stale,
+                reserved: HashMap::new(),
+            }),
+            journal: Some(journal),
+        })
+    }
+
+    pub async fn snapshot(&self) -> HashMap<String, GameRoom> {
+        self.state.lock().await.rooms.clone()
+    }
+
+    /// 查找同一应用、凭证及身份码的活跃场次
+    pub async fn find(&self, app_id: i64, access_key: &str, code: &str) -> Option<GameRoom> {
+        let state = self.state.lock().await;
+        state
+            .rooms
+            .values()
+            .find(|r| r.app_id == app_id && r.access_key == access_key && r.code.expose() == code)
+            .cloned()
+    }
+
+    pub async fn insert(&self, room: GameRoom) {
+        let mut state = self.state.lock().await;
+        state.rooms.insert(room.game_id.clone(), room);
+        self.persist(&state).await;
+    }
+
+    /// 应用的场次数（含预留）未达上限时预留一个名额
+    ///
+    /// 成功后须调用insert_reserved或cancel_reservation
+    pub async fn reserve(&self, app_id: i64, limit: Option<usize>) -> bool {
+        let mut state = self.state.lock().await;
+        let reserved = state.reserved.get(&app_id).copied().unwrap_or_default();
+        if let Some(limit) = limit {
+            let rooms = state.rooms.values().filter(|r| r.app_id == app_id).count();
+            if rooms + reserved >= limit {
+                return false;
+            }
+        }
+        state.reserved.insert(app_id, reserved + 1);
+        true
+    }
+
+    /// 将预留的名额转为活跃场次
+    pub async fn insert_reserved(&self, room: GameRoom) {
+        let mut state = self.state.lock().await;
+        state.release(room.app_id);
+        state.rooms.insert(room.game_id.clone(), room);
+        self.persist(&state).await;
+    }
+
+    /// 开启失败时释放预留的名额
+    pub async fn cancel_reservation(&self, app_id: i64) {
+        self.state.lock().await.release(app_id);
+    }
+
+    pub async fn remove(&self, game_id: &str) -> Option<GameRoom> {
+        let mut state = self.state.lock().await;
+        let room = state.rooms.remove(game_id);
+        if room.is_some() {
+            self.persist(&state).await;
+        }
+        room
+    }
+
+    pub async fn drain(&self) -> Vec<GameRoom> {
+        let mut state = self.state.lock().await;
+        let drained = state.rooms.drain().map(|(_, room)| room).collect();
+        self.persist(&state).await;
+        drained
+    }
+
+    /// 取出遗留场次
+    pub async fn take_stale(&self) -> Vec<GameRoom> {
+        let mut state = self.state.lock().await;
+        let stale = std::mem::take(&mut state.stale);
+        self.persist(&state).await;
+        stale
+    }
+
+    /// 将遗留场次恢复为活跃场次
+    pub async fn resume_stale(&self) -> Vec<GameRoom> {
+        let mut state = self.state.lock().await;
+        let stale = std::mem::take(&mut state.stale);
+        for room in stale.iter() {
+            state.rooms.insert(room.game_id.clone(), room.clone());
+        }
+        self.persist(&state).await;
+        stale
+    }
+
+    /// 在阻塞线程中写入记录，写入完成前保持锁以保证记录顺序
+    async fn persist(&self, state: &RoomsState) {
+        if let Some(journal) = &self.journal {
+            let rooms = state
+                .rooms
+                .values()
+                .chain(state.stale.iter())
+                .cloned()
+                .collect::<Vec<GameRoom>>();
+            let writer = journal.clone();
+            let error = match tokio::task::spawn_blocking(move || writer.save(rooms.iter())).await {
+                Ok(Ok(())) => return,
+                Ok(Err(e)) => e.to_string(),
+                Err(e) => e.to_string(),
+            };
+            tracing::error!(path = ?journal.path(), %error, "failed to save journal");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GameRooms;
+    use crate::{journal::SessionJournal, GameRoom};
+
+    fn room(game_id: &str) -> GameRoom {
+        GameRoom {
+            app_id: 1,
+            game_id: game_id.to_string(),
+            code: "code".into(),
+            access_key: String::new(),
+            project: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_journal_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rooms.json");
+        let journal = SessionJournal::new(&path);
+        journal.save([room("stale")].iter()).unwrap();
+        let rooms = GameRooms::with_journal(journal.clone()).unwrap();
+        rooms.insert(room("active")).await;
+        // 遗留场次处理前保留在记录中
+        assert_eq!(journal.load().unwrap().len(), 2);
+        let resumed = rooms.resume_stale().await;
+        assert_eq!(resumed.len(), 1);
+        assert_eq!(rooms.snapshot().await.len(), 2);
+        rooms.remove("active").await;
+        let saved = journal.load().unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].game_id, "stale");
+        rooms.drain().await;
+        assert!(journal.load().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_find() {
+        let rooms = GameRooms::default();
+        rooms
+            .insert(GameRoom {
+                access_key: "key1".to_string(),
+                ..room("game")
+            })
+            .await;
+        assert!(rooms.find(1, "key1", "code").await.is_some());
+        // 其它凭证开启的场次不会被复用
+        assert!(rooms.find(1, "key2", "code").await.is_none());
+        assert!(rooms.find(2, "key1", "code").await.is_none());
+        assert!(rooms.find(1, "key1", "other").await.is_none());
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    agent::AgentPool,
+    error::ServiceError,
+    event::{emit, ApiServiceEvent, EventHandles},
+    project::end_room,
+    rooms::GameRooms,
+    GameRoom,
+};
+use tokio::time::Duration;
+
+/// 默认的关闭等待时间
+pub const DEFAULT_SHUTDOWN_DEADLINE: Duration = Duration::from_secs(10);
+
+/// 单个场次的结束结果
+#[derive(Debug)]
+pub struct RoomEndResult {
+    pub room: GameRoom,
+    pub result: Result<(), ServiceError>,
+}
+
+/// 并发关闭所有场次的长连接并结束场次，每个场次最多等待deadline
+pub(crate) async fn end_rooms(
+    agents: &AgentPool,
+    game_rooms: &GameRooms,
+    event_handles: &EventHandles,
+    deadline: Duration,
+) -> Vec<RoomEndResult> {
+    // 先取出全部场次再释放锁，避免请求期间持有锁
+    let rooms = game_rooms.drain().await;
+    let futures = rooms.into_iter().map(|room| async move {
+        let Some(api_agent) = agents.get(&room.access_key) else {
+            let result = Err(ServiceError::UnknownCredential(room.access_key.clone()));
+            return RoomEndResult { room, result };
+        };
+        let project = room.project.upgrade();
+        let end = async {
+            if let Some(project) = &project {
+                project.agent().stop().await;
+            }
+            end_room(&api_agent, event_handles, room.clone()).await
+        };
+        let result = match tokio::time::timeout(deadline, end).await {
+            Ok(result) => result,
+            Err(_) => {
+                let error = Some(ServiceError::Timeout.to_string());
+                let event = ApiServiceEvent::ProjectEnded {
+                    room: room.clone(),
+                    error,
+                };
+                emit(event_handles, event).await;
+                Err(ServiceError::Timeout)
+            }
+        };
+        RoomEndResult { room, result }
+    });
+    futures::future::join_all(futures).await
+}
+
+/// 等待Ctrl-C或SIGTERM信号
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::end_rooms;
+    use crate::{
+        agent::{AgentPool, ApiAgent},
+        auth::Auth,
+        error::ServiceError,
+        project::test_project,
+        retry::RetryPolicy,
+        rooms::GameRooms,
+        ApiService, GameRoom,
+    };
+    use std::sync::Arc;
+    use tokio::{
+        net::TcpListener,
+        sync::RwLock,
+        time::{Duration, Instant},
+    };
+
+    #[tokio::test]
+    async fn test_end_no_rooms() {
+        let agent = AgentPool::new(ApiAgent::new(Auth::new("key", "secret").unwrap()));
+        let rooms = GameRooms::default();
+        let handles = Arc::new(RwLock::new(Vec::new()));
+        let results = end_rooms(&agent, &rooms, &handles, Duration::from_secs(1)).await;
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_end_rooms_deadline() {
+        // 接受连接但从不响应的接口
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let mut conns = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                conns.push(conn);
+            }
+        });
+        let api_agent = ApiAgent::new(Auth::new("key", "secret").unwrap())
+            .with_base_url(base_url)
+            .with_retry_policy(RetryPolicy::none());
+        let service = ApiService::from_agent(api_agent);
+        let mut projects = Vec::new();
+        for i in 0..3 {
+            let project = test_project(&service, &format!("game{}", i));
+            let room = GameRoom {
+                app_id: 1,
+                game_id: project.game_id(),
+                code: format!("code{}", i).into(),
+                access_key: "key".to_string(),
+                project: project.link(),
+            };
+            service.game_rooms.insert(room).await;
+            projects.push(project);
+        }
+        let deadline = Duration::from_millis(300);
+        let start = Instant::now();
+        let results = end_rooms(
+            &service.agents,
+            &service.game_rooms,
+            &service.event_handles,
+            deadline,
+        )
+        .await;
+        // 并发结束，总耗时接近单个deadline
+        assert!(start.elapsed() < deadline * 2);
+        assert_eq!(results.len(), 3);
+        let mut game_ids: Vec<_> = results.iter().map(|r| r.room.game_id.clone()).collect();
+        game_ids.sort();
+        assert_eq!(game_ids, ["game0", "game1", "game2"]);
+        for result in &results {
+            assert!(matches!(result.result, Err(ServiceError::Timeout)));
+        }
+        assert!(projects.iter().all(|p| !p.agent().is_started()));
+        assert!(service.game_rooms.drain().await.is_empty());
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_end_rooms_failed() {
+        // 已关闭的端口，请求立即失败
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let api_agent = ApiAgent::new(Auth::new("key", "secret").unwrap())
+            .with_base_url(base_url)
+            .with_retry_policy(RetryPolicy::none());
+        let service = ApiService::from_agent(api_agent);
+        let project = test_project(&service, "game");
+        let room = GameRoom {
+            app_id: 1,
+            game_id: "game".to_string(),
+            code: "code".into(),
+            access_key: "key".to_string(),
+            project: project.link(),
+        };
+        service.game_rooms.insert(room).await;
+        let results = end_rooms(
+            &service.agents,
+            &service.game_rooms,
+            &service.event_handles,
+            Duration::from_secs(5),
+        )
+        .await;
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0].result,
+            Err(ServiceError::ReqwestError(_))
+        ));
+    }
+}
@@ -0,0 +1,589 @@
+use crate::{
+    handle::LiveCmdHandle, proto::*, recorder::Frame, testkit::encode, CmdAgent, SessionContext,
+};
+use rand::{distributions::WeightedIndex, prelude::*, rngs::StdRng};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::{Instant, MissedTickBehavior};
+
+/// 各类消息的生成速率（条/秒）
+#[derive(Debug, Clone)]
+pub struct EventRates {
+    pub dm: f64,
+    pub send_gift: f64,
+    pub super_chat: f64,
+    pub guard: f64,
+    pub like: f64,
+}
+
+impl Default for EventRates {
+    fn default() -> Self {
+        Self {
+            dm: 10.0,
+            send_gift: 2.0,
+            super_chat: 0.1,
+            guard: 0.05,
+            like: 5.0,
+        }
+    }
+}
+
+impl EventRates {
+    pub fn total(&self) -> f64 {
+        self.weights().iter().sum()
+    }
+
+    fn weights(&self) -> [f64; 5] {
+        [
+            self.dm,
+            self.send_gift,
+            self.super_chat,
+            self.guard,
+            self.like,
+        ]
+        .map(|r| if r.is_finite() { r.max(0.0) } else { 0.0 })
+    }
+}
+
+/// 发送消息的用户分布
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserDistribution {
+    /// 每个用户概率相同
+    Uniform,
+    /// 少数用户发送大部分消息，参数越大越集中（通常取1.0左右）
+    Zipf(f64),
+}
+
+/// 模拟消息生成配置
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    /// 直播间号
+    pub room_id: i64,
+    /// 用户数量
+    pub users: usize,
+    pub distribution: UserDistribution,
+    pub rates: EventRates,
+    /// 礼物开始连击的概率
+    pub combo_probability: f64,
+    /// 单次连击的最大次数
+    pub max_combo: i64,
+    /// 随机种子，相同种子生成相同的消息序列
+    pub seed: Option<u64>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            room_id: 1,
+            users: 1000,
+            distribution: UserDistribution::Zipf(1.0),
+            rates: EventRates::default(),
+            combo_probability: 0.3,
+            max_combo: 10,
+            seed: None,
+        }
+    }
+}
+
+/// 模拟生成的消息
+#[derive(Debug, Clone)]
+pub enum SyntheticEvent {
+    Dm(CDM),
+    SendGift(CSendGift),
+    SuperChat(CSuperChat),
+    Guard(CGuard),
+    Like(CLike),
+}
+
+impl SyntheticEvent {
+    pub fn cmd(&self) -> &'static str {
+        match self {
+            SyntheticEvent::Dm(_) => LIVE_OPEN_PLATFORM_DM,
+            SyntheticEvent::SendGift(_) => LIVE_OPEN_PLATFORM_SEND_GIFT,
+            SyntheticEvent::SuperChat(_) => LIVE_OPEN_PLATFORM_SUPER_CHAT,
+            SyntheticEvent::Guard(_) => LIVE_OPEN_PLATFORM_GUARD,
+            SyntheticEvent::Like(_) => LIVE_OPEN_PLATFORM_LIKE,
+        }
+    }
+
+    /// 编码为服务端推送的弹幕消息包（Proto.Operation==5）
+    pub fn to_frame(&self) -> Vec<u8> {
+        match self {
+            SyntheticEvent::Dm(c) => encode(c),
+            SyntheticEvent::SendGift(c) => encode(c),
+            SyntheticEvent::SuperChat(c) => encode(c),
+            SyntheticEvent::Guard(c) => encode(c),
+            SyntheticEvent::Like(c) => encode(c),
+        }
+    }
+
+    /// 直接交给Cmd处理对象
+    pub async fn dispatch(&self, handles: &[Arc<dyn LiveCmdHandle>], ctx: &SessionContext) {
+        for handle in handles {
+            let ctx = ctx.clone();
+            match self {
+                SyntheticEvent::Dm(c) => handle.handle_dm(c.clone(), ctx).await,
+                SyntheticEvent::SendGift(c) => handle.handle_send_gift(c.clone(), ctx).await,
+                SyntheticEvent::SuperChat(c) => handle.handle_super_chat(c.clone(), ctx).await,
+                SyntheticEvent::Guard(c) => handle.handle_guard(c.clone(), ctx).await,
+                SyntheticEvent::Like(c) => handle.handle_like(c.clone(), ctx).await,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct User {
+    uid: i64,
+    open_id: String,
+    union_id: String,
+    uname: String,
+    uface: String,
+    guard_level: i64,
+    fans_medal_level: i64,
+    fans_medal_wearing_status: bool,
+}
+
+struct Combo {
+    user: usize,
+    gift: (i64, &'static str, i64),
+    combo_id: String,
+    count: i64,
+    remaining: i64,
+}
+
+const GIFTS: [(i64, &str, i64); 4] = [
+    (31036, "小花花", 100),
+    (31039, "牛哇牛哇", 100),
+    (31531, "这个好诶", 1000),
+    (31216, "小电视飞船", 1245000),
+];
+const MESSAGES: [&str; 6] = ["哈哈哈", "666", "来了来了", "好耶", "主播晚上好", "？？？"];
+
+/// 模拟消息生成，用于压测及演示
+pub struct EventGenerator {
+    config: GeneratorConfig,
+    rng: StdRng,
+    users: Vec<User>,
+    user_index: Option<WeightedIndex<f64>>,
+    kind_index: Option<WeightedIndex<f64>>,
+    combo: Option<Combo>,
+    message_id: i64,
+}
+
+impl EventGenerator {
+    pub fn new(config: GeneratorConfig) -> Self {
+        let mut rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let users = (0..config.users.max(1))
+            .map(|i| {
+                let fans_medal_level = rng.gen_range(0..=30);
+                User {
+                    uid: 10000 + i as i64,
+                    open_id: format!("{:032x}", rng.gen::<u128>()),
+                    union_id: format!("U_{:032x}", rng.gen::<u128>()),
+                    uname: format!("user_{}", i),
+                    uface: format!("https://i0.hdslb.com/bfs/face/{}.jpg", i),
+                    guard_level: *[0, 0, 0, 0, 3, 3, 2, 1].choose(&mut rng).unwrap(),
+                    fans_medal_level,
+                    fans_medal_wearing_status: fans_medal_level > 0,
+                }
+            })
+            .collect::<Vec<User>>();
+        let user_index = match config.distribution {
+            UserDistribution::Uniform => None,
+            UserDistribution::Zipf(s) => {
+                WeightedIndex::new((1..=users.len()).map(|k| 1.0 / (k as f64).powf(s))).ok()
+            }
+        };
+        let kind_index = WeightedIndex::new(config.rates.weights()).ok();
+        Self {
+            config,
+            rng,
+            users,
+            user_index,
+            kind_index,
+            combo: None,
+            message_id: 0,
+        }
+    }
+
+    pub fn config(&self) -> &GeneratorConfig {
+        &self.config
+    }
+
+    /// 按速率比例生成下一条消息，速率全为0时生成弹幕
+    pub fn next_event(&mut self) -> SyntheticEvent {
+        let kind = match &self.kind_index {
+            Some(index) => index.sample(&mut self.rng),
+            None => 0,
+        };
+        match kind {
+            1 => SyntheticEvent::SendGift(self.send_gift()),
+            2 => SyntheticEvent::SuperChat(self.super_chat()),
+            3 => SyntheticEvent::Guard(self.guard()),
+            4 => SyntheticEvent::Like(self.like()),
+            _ => SyntheticEvent::Dm(self.dm()),
+        }
+    }
+
+    /// 生成n条消息
+    pub fn events(&mut self, n: usize) -> Vec<SyntheticEvent> {
+        (0..n).map(|_| self.next_event()).collect()
+    }
+
+    /// 生成n个数据包，时间戳按总速率间隔，可用于CmdAgent::replay
+    pub fn frames(&mut self, n: usize) -> Vec<Frame> {
+        let start = now_ms();
+        let interval = 1000.0 / self.config.rates.total().max(f64::MIN_POSITIVE);
+        (0..n)
+            .map(|i| Frame {
+                timestamp_ms: start + (i as f64 * interval) as u64,
+                bytes: self.next_event().to_frame(),
+            })
+            .collect()
+    }
+
+    /// 按配置的速率持续生成消息并交给CmdAgent的Cmd处理对象，返回生成的消息数量
+    ///
+    /// 与长连接收到的数据包相同，消息按顺序依次交给各处理对象并等待处理完成，
+    /// 处理较慢时消息会积压到下一轮一并发送，总数仍为速率乘以持续时间
+    pub async fn run(&mut self, agent: &CmdAgent, duration: Duration) -> usize {
+        let ctx = agent.params().session;
+        let rate = self.config.rates.total();
+        let start = Instant::now();
+        // 处理耗时计入等待间隔，处理超过间隔时立即开始下一轮
+        let mut ticker = tokio::time::interval(Duration::from_millis(10));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut sent = 0usize;
+        loop {
+            ticker.tick().await;
+            let elapsed = start.elapsed().min(duration);
+            let due = (elapsed.as_secs_f64() * rate) as usize;
+            if due > sent {
+                let handles = agent.cmd_handles.read().await.clone();
+                for _ in sent..due {
+                    self.next_event().dispatch(&handles, &ctx).await;
+                }
+                sent = due;
+            }
+            if elapsed >= duration {
+                return sent;
+            }
+        }
+    }
+
+    fn user(&mut self) -> usize {
+        match &self.user_index {
+            Some(index) => index.sample(&mut self.rng),
+            None => self.rng.gen_range(0..self.users.len()),
+        }
+    }
+
+    fn next_msg_id(&mut self) -> String {
+        format!("{:032x}", self.rng.gen::<u128>())
+    }
+
+    fn dm(&mut self) -> CDM {
+        let user = self.user();
+        let user = self.users[user].clone();
+        CDM {
+            uname: user.uname,
+            uid: user.uid,
+            open_id: user.open_id,
+            union_id: user.union_id,
+            uface: user.uface,
+            timestamp: now_secs(),
+            room_id: self.config.room_id,
+            msg: MESSAGES.choose(&mut self.rng).unwrap().to_string(),
+            msg_id: self.next_msg_id(),
+            guard_level: user.guard_level,
+            fans_medal_wearing_status: user.fans_medal_wearing_status,
+            fans_medal_name: "粉丝团".to_string(),
+            fans_medal_level: user.fans_medal_level,
+            ..Default::default()
+        }
+    }
+
+    fn send_gift(&mut self) -> CSendGift {
+        // 继续未结束的连击
+        let combo = match self.combo.take() {
+            Some(mut combo) if combo.remaining > 0 => {
+                combo.count += 1;
+                combo.remaining -= 1;
+                combo
+            }
+            _ => {
+                let user = self.user();
+                let gift = *GIFTS.choose(&mut self.rng).unwrap();
+                let remaining = if self
+                    .rng
+                    .gen_bool(self.config.combo_probability.clamp(0.0, 1.0))
+                {
+                    self.rng.gen_range(1..self.config.max_combo.max(2))
+                } else {
+                    0
+                };
+                Combo {
+                    user,
+                    gift,
+                    combo_id: self.next_msg_id(),
+                    count: 1,
+                    remaining,
+                }
+            }
+        };
+        let user = self.users[combo.user].clone();
+        let combo_gift = combo.count > 1 || combo.remaining > 0;
+        let gift = CSendGift {
+            room_id: self.config.room_id,
+            uid: user.uid,
+            open_id: user.open_id,
+            union_id: user.union_id,
+            uname: user.uname,
+            uface: user.uface,
+            gift_id: combo.gift.0,
+            gift_name: combo.gift.1.to_string(),
+            gift_num: 1,
+            price: combo.gift.2,
+            paid: combo.gift.2 > 100,
+            fans_medal_level: user.fans_medal_level,
+            fans_medal_name: "粉丝团".to_string(),
+            fans_medal_wearing_status: user.fans_medal_wearing_status,
+            guard_level: user.guard_level,
+            timestamp: now_secs(),
+            msg_id: self.next_msg_id(),
+            combo_gift,
+            combo_info: if combo_gift {
+                CComboInfo {
+                    combo_base_num: 1,
+                    combo_count: combo.count,
+                    combo_id: combo.combo_id.clone(),
+                    combo_timeout: 3,
+                }
+            } else {
+                Default::default()
+            },
+            ..Default::default()
+        };
+        if combo.remaining > 0 {
+            self.combo = Some(combo);
+        }
+        gift
+    }
+
+    fn super_chat(&mut self) -> CSuperChat {
+        let user = self.user();
+        let user = self.users[user].clone();
+        let rmb = *[30, 50, 100, 500, 1000].choose(&mut self.rng).unwrap();
+        let timestamp = now_secs();
+        self.message_id += 1;
+        CSuperChat {
+            room_id: self.config.room_id,
+            uid: user.uid,
+            open_id: user.open_id,
+            union_id: user.union_id,
+            uname: user.uname,
+            uface: user.uface,
+            message_id: self.message_id,
+            message: MESSAGES.choose(&mut self.rng).unwrap().to_string(),
+            rmb,
+            timestamp,
+            start_time: timestamp,
+            end_time: timestamp + 60,
+            guard_level: user.guard_level,
+            fans_medal_level: user.fans_medal_level,
+            fans_medal_name: "粉丝团".to_string(),
+            fans_medal_wearing_status: user.fans_medal_wearing_status,
+            msg_id: self.next_msg_id(),
+        }
+    }
+
+    fn guard(&mut self) -> CGuard {
+        let user = self.user();
+        let user = self.users[user].clone();
+        CGuard {
+            user_info: CUserInfo {
+                uid: user.uid,
+                open_id: user.open_id,
+                union_id: user.union_id,
+                uname: user.uname,
+                uface: user.uface,
+            },
+            guard_level: *[3, 3, 3, 2, 1].choose(&mut self.rng).unwrap(),
+            guard_num: 1,
+            guard_unit: "月".to_string(),
+            fans_medal_level: user.fans_medal_level,
+            fans_medal_name: "粉丝团".to_string(),
+            fans_medal_wearing_status: user.fans_medal_wearing_status,
+            room_id: self.config.room_id,
+            msg_id: self.next_msg_id(),
+            timestamp: now_secs(),
+        }
+    }
+
+    fn like(&mut self) -> CLike {
+        let user = self.user();
+        let user = self.users[user].clone();
+        CLike {
+            like_text: format!("{}点赞了", user.uname),
+            uname: user.uname,
+            uid: user.uid,
+            open_id: user.open_id,
+            union_id: user.union_id,
+            uface: user.uface,
+            timestamp: now_secs(),
+            room_id: self.config.room_id,
+            like_conut: self.rng.gen_range(1..=10),
+            fans_medal_wearing_status: user.fans_medal_wearing_status,
+            fans_medal_name: "粉丝团".to_string(),
+            fans_medal_level: user.fans_medal_level,
+        }
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn now_secs() -> i64 {
+    (now_ms() / 1000) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        replay::ReplaySpeed,
+        testkit::{RecordedEvent, RecordingHandler},
+    };
+    use serde::Serialize;
+    use serde_json::Value;
+
+    #[test]
+    fn test_generator_combo() {
+        let mut generator = EventGenerator::new(GeneratorConfig {
+            users: 10,
+            rates: EventRates {
+                dm: 0.0,
+                send_gift: 1.0,
+                super_chat: 0.0,
+                guard: 0.0,
+                like: 0.0,
+            },
+            combo_probability: 1.0,
+            max_combo: 5,
+            seed: Some(1),
+            ..Default::default()
+        });
+        let gifts = generator
+            .events(20)
+            .into_iter()
+            .map(|e| match e {
+                SyntheticEvent::SendGift(g) => g,
+                e => panic!("unexpected event {:?}", e),
+            })
+            .collect::<Vec<CSendGift>>();
+        assert!(gifts.iter().all(|g| g.combo_gift));
+        // 同一连击的用户、道具及id相同，次数递增
+        for pair in gifts.windows(2) {
+            if pair[0].combo_info.combo_id == pair[1].combo_info.combo_id {
+                assert_eq!(pair[0].uid, pair[1].uid);
+                assert_eq!(pair[0].gift_id, pair[1].gift_id);
+                assert_eq!(
+                    pair[0].combo_info.combo_count + 1,
+                    pair[1].combo_info.combo_count
+                );
+            } else {
+                assert_eq!(pair[1].combo_info.combo_count, 1);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_frames() {
+        let config = GeneratorConfig {
+            seed: Some(2),
+            ..Default::default()
+        };
+        let events = EventGenerator::new(config.clone()).events(100);
+        // 相同种子生成相同的消息序列
+        let same = EventGenerator::new(config.clone()).events(100);
+        assert_eq!(
+            events.iter().map(synthetic).collect::<Vec<_>>(),
+            same.iter().map(synthetic).collect::<Vec<_>>()
+        );
+
+        let frames = EventGenerator::new(config).frames(100);
+        let agent = CmdAgent::new(Default::default());
+        let handler = Arc::new(RecordingHandler::new());
+        agent.cmd_handles.write().await.push(handler.clone());
+        assert_eq!(agent.replay(frames, ReplaySpeed::Max).await, 100);
+        assert_eq!(
+            handler.events().iter().map(recorded).collect::<Vec<_>>(),
+            events.iter().map(synthetic).collect::<Vec<_>>()
+        );
+    }
+
+    /// 序列化消息并去掉与当前时间相关的字段
+    fn fields<T: Serialize>(cmd: &str, data: &T) -> (String, Value) {
+        let mut value = serde_json::to_value(data).unwrap();
+        if let Some(map) = value.as_object_mut() {
+            for key in ["timestamp", "start_time", "end_time"] {
+                map.remove(key);
+            }
+        }
+        (cmd.to_string(), value)
+    }
+
+    fn synthetic(event: &SyntheticEvent) -> (String, Value) {
+        match event {
+            SyntheticEvent::Dm(c) => fields(event.cmd(), c),
+            SyntheticEvent::SendGift(c) => fields(event.cmd(), c),
+            SyntheticEvent::SuperChat(c) => fields(event.cmd(), c),
+            SyntheticEvent::Guard(c) => fields(event.cmd(), c),
+            SyntheticEvent::Like(c) => fields(event.cmd(), c),
+        }
+    }
+
+    fn recorded(event: &RecordedEvent) -> (String, Value) {
+        match event {
+            RecordedEvent::Dm(c) => fields(LIVE_OPEN_PLATFORM_DM, c),
+            RecordedEvent::SendGift(c) => fields(LIVE_OPEN_PLATFORM_SEND_GIFT, c),
+            RecordedEvent::SuperChat(c) => fields(LIVE_OPEN_PLATFORM_SUPER_CHAT, c),
+            RecordedEvent::Guard(c) => fields(LIVE_OPEN_PLATFORM_GUARD, c),
+            RecordedEvent::Like(c) => fields(LIVE_OPEN_PLATFORM_LIKE, c),
+            e => panic!("unexpected event {:?}", e),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_run_rate() {
+        let mut generator = EventGenerator::new(GeneratorConfig {
+            rates: EventRates {
+                dm: 1000.0,
+                send_gift: 200.0,
+                super_chat: 0.0,
+                guard: 0.0,
+                like: 300.0,
+            },
+            seed: Some(1),
+            ..Default::default()
+        });
+        let agent = CmdAgent::new(Default::default());
+        let handler = Arc::new(RecordingHandler::new());
+        agent.cmd_handles.write().await.push(handler.clone());
+        let start = Instant::now();
+        let sent = generator.run(&agent, Duration::from_secs(2)).await;
+        assert_eq!(sent, 3000);
+        assert_eq!(handler.len(), 3000);
+        assert!(start.elapsed() < Duration::from_secs(2) + Duration::from_millis(20));
+    }
+}
@@ -1,26 +1,26 @@
 use async_trait::async_trait;
 
-use crate::{proto::*, CmdAgentParams};
+use crate::{proto::*, SessionContext};
 
 /// 解析后的Cmd处理
 #[async_trait]
 pub trait LiveCmdHandle: Send + Sync {
-    async fn handle_dm(&self, cmd: CDM, params: CmdAgentParams);
-    async fn handle_send_gift(&self, cmd: CSendGift, params: CmdAgentParams);
-    async fn handle_super_chat(&self, cmd: CSuperChat, params: CmdAgentParams);
-    async fn handle_super_chat_del(&self, cmd: CSuperChatDel, params: CmdAgentParams);
-    async fn handle_guard(&self, cmd: CGuard, params: CmdAgentParams);
-    async fn handle_like(&self, cmd: CLike, params: CmdAgentParams);
+    async fn handle_dm(&self, cmd: CDM, ctx: SessionContext);
+    async fn handle_send_gift(&self, cmd: CSendGift, ctx: SessionContext);
+    async fn handle_super_chat(&self, cmd: CSuperChat, ctx: SessionContext);
+    async fn handle_super_chat_del(&self, cmd: CSuperChatDel, ctx: SessionContext);
+    async fn handle_guard(&self, cmd: CGuard, ctx: SessionContext);
+    async fn handle_like(&self, cmd: CLike, ctx: SessionContext);
 }
 
 /// Proto数据处理
 #[async_trait]
 pub trait LiveCmdHandleOP: Send + Sync {
-    async fn handle(&self, proto: RawProto, params: CmdAgentParams);
+    async fn handle(&self, proto: RawProto, ctx: SessionContext);
 }
 
 /// 原始数据处理
 #[async_trait]
 pub trait LiveCmdHandleRAW: Send + Sync {
-    async fn handle(&self, bytes: Vec<u8>, params: CmdAgentParams);
+    async fn handle(&self, bytes: Vec<u8>, ctx: SessionContext);
 }
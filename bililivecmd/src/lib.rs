@@ -1,41 +1,82 @@
 use flate2::write::ZlibDecoder;
-use futures::{stream::SplitSink, SinkExt, StreamExt};
+use futures::{future::BoxFuture, stream::SplitSink, SinkExt, StreamExt};
 use handle::{LiveCmdHandle, LiveCmdHandleOP, LiveCmdHandleRAW};
 use proto::{
-    CGuard, CLike, CSendGift, CSuperChat, CSuperChatDel, LiveOpenPlatformCmd, RawProto, CDM,
-    LIVE_OPEN_PLATFORM_GUARD, LIVE_OPEN_PLATFORM_LIKE, LIVE_OPEN_PLATFORM_SEND_GIFT,
+    split_packets, CGuard, CLike, CSendGift, CSuperChat, CSuperChatDel, LiveOpenPlatformCmd,
+    RawProto, CDM, LIVE_OPEN_PLATFORM_GUARD, LIVE_OPEN_PLATFORM_LIKE, LIVE_OPEN_PLATFORM_SEND_GIFT,
     LIVE_OPEN_PLATFORM_SUPER_CHAT, LIVE_OPEN_PLATFORM_SUPER_CHAT_DEL,
 };
 use serde_json::Value;
 use std::io::prelude::*;
-use std::sync::Arc;
-use tokio::{net::TcpStream, sync::RwLock, time::Duration};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Instant;
+use tokio::{
+    net::TcpStream,
+    sync::{Mutex, RwLock},
+    task::JoinHandle,
+    time::Duration,
+};
 use tokio_tungstenite::{
     connect_async,
     tungstenite::{http::Uri, Message},
     MaybeTlsStream, WebSocketStream,
 };
+use tracing::{debug, error, info, trace, warn, Instrument};
 
 use crate::proto::LIVE_OPEN_PLATFORM_DM;
 
+pub mod generator;
 pub mod handle;
+pub mod metrics;
 pub mod proto;
+pub mod recorder;
+pub mod replay;
+pub mod secret;
 pub mod test_handle;
+pub mod testkit;
+
+pub use secret::Secret;
 
 pub struct CmdAgent {
-    is_working: bool,
-    pub params: CmdAgentParams,
+    is_working: Arc<AtomicBool>,
+    params: std::sync::RwLock<CmdAgentParams>,
     pub raw_handles: Arc<RwLock<Vec<Arc<dyn LiveCmdHandleRAW>>>>,
     pub op_handles: Arc<RwLock<Vec<Arc<dyn LiveCmdHandleOP>>>>,
     pub cmd_handles: Arc<RwLock<Vec<Arc<dyn LiveCmdHandle>>>>,
+    writer: Arc<Mutex<Option<Writer>>>,
+    tasks: std::sync::Mutex<Vec<JoinHandle<()>>>,
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct CmdAgentParams {
-    pub auth_body: String,
+    pub auth_body: Secret<String>,
     pub server_url: String,
     pub app_id: i64,
-    pub user_code: String,
+    pub user_code: Secret<String>,
+    pub session: SessionContext,
+}
+
+/// 场次上下文，随每次处理调用传入（不含auth_body等认证信息）
+#[derive(Debug, Clone, Default)]
+pub struct SessionContext {
+    pub app_id: i64,
+    /// 场次id
+    pub game_id: String,
+    /// 主播信息
+    pub anchor: AnchorContext,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AnchorContext {
+    pub room_id: i64,     // 主播房间号
+    pub uid: i64,         // 主播uid
+    pub open_id: String,  // 主播唯一标识
+    pub union_id: String, // 主播在同一开发者下的唯一标识
+    pub uname: String,    // 主播昵称
+    pub uface: String,    // 主播头像
 }
 
 type Writer = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
@@ -43,97 +84,163 @@ type Writer = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
 impl CmdAgent {
     pub fn new(params: CmdAgentParams) -> Self {
         CmdAgent {
-            params,
-            is_working: false,
+            params: std::sync::RwLock::new(params),
+            is_working: Arc::new(AtomicBool::new(false)),
             raw_handles: Arc::new(RwLock::new(Vec::new())),
             op_handles: Arc::new(RwLock::new(Vec::new())),
             cmd_handles: Arc::new(RwLock::new(Vec::new())),
+            writer: Arc::new(Mutex::new(None)),
+            tasks: std::sync::Mutex::new(Vec::new()),
         }
     }
 
     pub fn is_working(&self) -> bool {
-        self.is_working
+        self.is_working.load(Ordering::SeqCst)
+    }
+
+    /// 是否已调用start且未停止（连接断开后仍为true）
+    pub fn is_started(&self) -> bool {
+        !self.tasks.lock().unwrap().is_empty()
+    }
+
+    pub fn params(&self) -> CmdAgentParams {
+        self.params.read().unwrap().clone()
+    }
+
+    /// 更新参数，下次start时生效
+    pub fn set_params(&self, params: CmdAgentParams) {
+        *self.params.write().unwrap() = params;
+    }
+
+    pub async fn start(&self) {
+        let params = self.params();
+        let span = tracing::info_span!(
+            "cmd_agent",
+            room_id = params.session.anchor.room_id,
+            game_id = %params.session.game_id,
+        );
+        self.start_with(params).instrument(span).await;
     }
 
-    pub async fn start(&mut self) {
+    async fn start_with(&self, params: CmdAgentParams) {
         //构建websocket客户端
-        let server_uri = match Uri::try_from(self.params.server_url.clone()) {
+        let server_uri = match Uri::try_from(params.server_url.clone()) {
             Ok(uri) => uri,
             Err(e) => {
-                eprintln!("Invalid URI {} {:?}", e, self.params);
+                error!(error = %e, server_url = %params.server_url, "invalid websocket uri");
                 return;
             }
         };
         let (ws_stream, _) = match connect_async(server_uri).await {
             Ok(result) => result,
             Err(e) => {
-                eprintln!("WebSocket connection failed {} {:?}", e, self.params);
+                error!(error = %e, server_url = %params.server_url, "websocket connection failed");
                 return;
             }
         };
+        info!(server_url = %params.server_url, "websocket connected");
         let (writer, read) = ws_stream.split();
         // 接收消息
         let raw_handles = Arc::clone(&self.raw_handles);
         let op_handles = Arc::clone(&self.op_handles);
         let cmd_handles = Arc::clone(&self.cmd_handles);
-        let agent_params = self.params.clone();
-        tokio::spawn(async move {
-            let mut reader = read;
-            while let Some(message) = reader.next().await {
-                match message {
-                    Ok(msg) => {
-                        if let Message::Binary(bytes) = msg {
-                            let raw_handles = Arc::clone(&raw_handles);
-                            let op_handles = Arc::clone(&op_handles);
-                            let cmd_handles = Arc::clone(&cmd_handles);
-                            handle(
-                                bytes,
-                                &raw_handles,
-                                &op_handles,
-                                &cmd_handles,
-                                agent_params.clone(),
-                            )
-                            .await;
-                        } else if let Message::Ping(_p) = msg {
-                        } else {
-                            eprintln!("No Binary Data {:?}", msg);
+        let session = params.session.clone();
+        let is_working = Arc::clone(&self.is_working);
+        let reader_task = tokio::spawn(
+            async move {
+                let mut reader = read;
+                while let Some(message) = reader.next().await {
+                    match message {
+                        Ok(msg) => {
+                            if let Message::Binary(bytes) = msg {
+                                let raw_handles = Arc::clone(&raw_handles);
+                                let op_handles = Arc::clone(&op_handles);
+                                let cmd_handles = Arc::clone(&cmd_handles);
+                                handle(
+                                    bytes,
+                                    &raw_handles,
+                                    &op_handles,
+                                    &cmd_handles,
+                                    session.clone(),
+                                )
+                                .await;
+                            } else if let Message::Ping(_p) = msg {
+                            } else {
+                                debug!(message = ?msg, "ignored non-binary message");
+                            }
                         }
+                        Err(e) => warn!(error = %e, "failed to receive message"),
                     }
-                    Err(e) => eprintln!("Failed to receive {e}"),
                 }
+                info!("websocket closed");
+                is_working.store(false, Ordering::SeqCst);
             }
-        });
+            .in_current_span(),
+        );
+        self.tasks.lock().unwrap().push(reader_task);
         // 发送AUTH包
-        let writer = self.send_auth(writer).await;
-        if writer.is_err() {
-            eprintln!("Failed auth {:?}", self.params);
+        let writer = Self::send_auth(writer, params.auth_body.expose()).await;
+        if let Err(e) = &writer {
+            error!(error = %e, "failed to send auth");
             return;
         }
+        *self.writer.lock().await = writer.ok();
         // 发送心跳
-        let mut writer = writer.unwrap();
-        let agent_params = self.params.clone();
-        tokio::spawn(async move {
-            loop {
-                println!("cmd heartbeat");
-                let proto = RawProto::new(2, Vec::new());
-                let result = writer.send(Message::Binary(proto.clone().into())).await;
-                if result.is_err() {
-                    eprintln!("Failed to send message {:?} {:?}", proto, agent_params);
+        let writer = Arc::clone(&self.writer);
+        let heartbeat_task = tokio::spawn(
+            async move {
+                loop {
+                    trace!("cmd heartbeat");
+                    let proto = RawProto::new(2, Vec::new());
+                    let result = match writer.lock().await.as_mut() {
+                        Some(w) => w.send(Message::Binary(proto.into())).await,
+                        None => break,
+                    };
+                    if let Err(e) = result {
+                        warn!(error = %e, "failed to send heartbeat");
+                    }
+                    tokio::time::sleep(Duration::from_secs(30)).await;
                 }
-                tokio::time::sleep(Duration::from_secs(30)).await;
             }
-        });
+            .in_current_span(),
+        );
+        self.tasks.lock().unwrap().push(heartbeat_task);
         //正常运行标识
-        self.is_working = true;
+        self.is_working.store(true, Ordering::SeqCst);
+    }
+
+    /// 关闭长连接并停止接收及心跳
+    pub async fn stop(&self) {
+        if let Some(mut writer) = self.writer.lock().await.take() {
+            let _ = writer.send(Message::Close(None)).await;
+            let _ = writer.close().await;
+        }
+        self.abort();
+    }
+
+    /// 使用新的参数（如重新开启场次后的auth_body）重新建立长连接
+    pub async fn reconnect(&self, params: CmdAgentParams) {
+        metrics::reconnect();
+        self.stop().await;
+        self.set_params(params);
+        self.start().await;
+    }
+
+    /// 立即中止后台任务（不发送Close帧）
+    pub fn abort(&self) {
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+        self.is_working.store(false, Ordering::SeqCst);
     }
 
     async fn send_auth(
-        &self,
         mut write: Writer,
+        auth_body: &str,
     ) -> Result<Writer, tokio_tungstenite::tungstenite::Error> {
         write
             .send(Message::Binary(
-                RawProto::new(7, self.params.auth_body.clone().as_bytes().to_vec()).into(),
+                RawProto::new(7, auth_body.as_bytes().to_vec()).into(),
             ))
             .await?;
         Ok(write)
@@ -141,157 +248,253 @@ impl CmdAgent {
 }
 
 ///消息处理
-#[allow(unused_must_use, clippy::let_underscore_future)]
 async fn handle(
     bytes: Vec<u8>,
     raw_handles: &Arc<RwLock<Vec<Arc<dyn LiveCmdHandleRAW>>>>,
     op_handles: &Arc<RwLock<Vec<Arc<dyn LiveCmdHandleOP>>>>,
     cmd_handles: &Arc<RwLock<Vec<Arc<dyn LiveCmdHandle>>>>,
-    params: CmdAgentParams,
+    ctx: SessionContext,
 ) {
     //处理原始数据
     for raw in raw_handles.read().await.iter() {
         let bytes = bytes.clone();
-        let params = params.clone();
-        raw.handle(bytes, params).await;
+        let ctx = ctx.clone();
+        let start = Instant::now();
+        raw.handle(bytes, ctx).await;
+        metrics::handler_latency("raw", start);
     }
     //处理Proto数据
-    if let Ok(proto) = RawProto::try_from(bytes) {
-        if proto.version == 2 {
-            //处理压缩
-            let mut writer = Vec::new();
-            let mut z = ZlibDecoder::new(writer);
-            let r = z.write_all(&proto.body);
-            if r.is_err() {
-                return;
-            }
-            let r = z.finish();
-            if r.is_err() {
-                return;
+    handle_packets(&bytes, op_handles, cmd_handles, &ctx).await;
+}
+
+/// 处理一个或多个连续的数据包，压缩包解压后递归处理
+fn handle_packets<'a>(
+    bytes: &'a [u8],
+    op_handles: &'a Arc<RwLock<Vec<Arc<dyn LiveCmdHandleOP>>>>,
+    cmd_handles: &'a Arc<RwLock<Vec<Arc<dyn LiveCmdHandle>>>>,
+    ctx: &'a SessionContext,
+) -> BoxFuture<'a, ()> {
+    Box::pin(async move {
+        for packet in split_packets(bytes) {
+            if let Ok(proto) = RawProto::try_from(packet.to_vec()) {
+                handle_proto(proto, op_handles, cmd_handles, ctx).await;
             }
-            writer = r.unwrap();
-            //递归消息处理
-            handle(writer, raw_handles, op_handles, cmd_handles, params.clone());
-            return;
         }
-        for op in op_handles.read().await.iter() {
-            let proto: RawProto = proto.clone();
-            let params = params.clone();
-            op.handle(proto, params).await;
+    })
+}
+
+async fn handle_proto(
+    proto: RawProto,
+    op_handles: &Arc<RwLock<Vec<Arc<dyn LiveCmdHandleOP>>>>,
+    cmd_handles: &Arc<RwLock<Vec<Arc<dyn LiveCmdHandle>>>>,
+    ctx: &SessionContext,
+) {
+    trace!(
+        operation = proto.operation,
+        version = proto.version,
+        len = proto.body.len(),
+        "frame"
+    );
+    metrics::frame(proto.operation);
+    if proto.version == 2 {
+        //处理压缩
+        let writer = Vec::new();
+        let mut z = ZlibDecoder::new(writer);
+        if let Err(e) = z.write_all(&proto.body) {
+            warn!(error = %e, operation = proto.operation, "zlib decompression failed");
+            metrics::decompress_failed();
+            return;
         }
-        //弹幕消息包
-        if proto.operation == 5 {
-            //处理解析后的Cmd
-            match String::from_utf8(proto.body) {
-                Ok(json) => match serde_json::from_str::<Value>(&json) {
-                    Ok(v) => {
-                        if let Some((_, v)) = v.as_object().and_then(|m| m.iter().next()) {
-                            if let Some(cmd) = v.as_str() {
-                                match cmd {
-                                    LIVE_OPEN_PLATFORM_DM => {
-                                        if let Ok(pcmd) =
-                                            serde_json::from_str::<LiveOpenPlatformCmd<CDM>>(&json)
-                                        {
-                                            for handle in cmd_handles.read().await.iter() {
-                                                let params = params.clone();
-                                                handle.handle_dm(pcmd.data.clone(), params).await;
-                                            }
+        let writer = match z.finish() {
+            Ok(writer) => writer,
+            Err(e) => {
+                warn!(error = %e, operation = proto.operation, "zlib decompression failed");
+                metrics::decompress_failed();
+                return;
+            }
+        };
+        //递归消息处理
+        handle_packets(&writer, op_handles, cmd_handles, ctx).await;
+        return;
+    }
+    for op in op_handles.read().await.iter() {
+        let proto: RawProto = proto.clone();
+        let ctx = ctx.clone();
+        let start = Instant::now();
+        op.handle(proto, ctx).await;
+        metrics::handler_latency("op", start);
+    }
+    //弹幕消息包
+    if proto.operation == 5 {
+        //处理解析后的Cmd
+        match String::from_utf8(proto.body) {
+            Ok(json) => match serde_json::from_str::<Value>(&json) {
+                Ok(v) => {
+                    if let Some((_, v)) = v.as_object().and_then(|m| m.iter().next()) {
+                        if let Some(cmd) = v.as_str() {
+                            debug!(cmd, "dispatch cmd");
+                            match cmd {
+                                LIVE_OPEN_PLATFORM_DM => {
+                                    if let Ok(pcmd) =
+                                        serde_json::from_str::<LiveOpenPlatformCmd<CDM>>(&json)
+                                    {
+                                        metrics::event_decoded(cmd);
+                                        for handle in cmd_handles.read().await.iter() {
+                                            let ctx = ctx.clone();
+                                            let start = Instant::now();
+                                            handle.handle_dm(pcmd.data.clone(), ctx).await;
+                                            metrics::handler_latency(cmd, start);
                                         }
                                     }
-                                    LIVE_OPEN_PLATFORM_SEND_GIFT => {
-                                        if let Ok(pcmd) =
-                                            serde_json::from_str::<LiveOpenPlatformCmd<CSendGift>>(
-                                                &json,
-                                            )
-                                        {
-                                            for handle in cmd_handles.read().await.iter() {
-                                                let params = params.clone();
-                                                handle
-                                                    .handle_send_gift(pcmd.data.clone(), params)
-                                                    .await;
-                                            }
+                                }
+                                LIVE_OPEN_PLATFORM_SEND_GIFT => {
+                                    if let Ok(pcmd) = serde_json::from_str::<
+                                        LiveOpenPlatformCmd<CSendGift>,
+                                    >(&json)
+                                    {
+                                        metrics::event_decoded(cmd);
+                                        for handle in cmd_handles.read().await.iter() {
+                                            let ctx = ctx.clone();
+                                            let start = Instant::now();
+                                            handle.handle_send_gift(pcmd.data.clone(), ctx).await;
+                                            metrics::handler_latency(cmd, start);
                                         }
                                     }
-                                    LIVE_OPEN_PLATFORM_SUPER_CHAT => {
-                                        if let Ok(pcmd) =
-                                            serde_json::from_str::<LiveOpenPlatformCmd<CSuperChat>>(
-                                                &json,
-                                            )
-                                        {
-                                            for handle in cmd_handles.read().await.iter() {
-                                                let params = params.clone();
-                                                handle
-                                                    .handle_super_chat(pcmd.data.clone(), params)
-                                                    .await;
-                                            }
+                                }
+                                LIVE_OPEN_PLATFORM_SUPER_CHAT => {
+                                    if let Ok(pcmd) = serde_json::from_str::<
+                                        LiveOpenPlatformCmd<CSuperChat>,
+                                    >(&json)
+                                    {
+                                        metrics::event_decoded(cmd);
+                                        for handle in cmd_handles.read().await.iter() {
+                                            let ctx = ctx.clone();
+                                            let start = Instant::now();
+                                            handle.handle_super_chat(pcmd.data.clone(), ctx).await;
+                                            metrics::handler_latency(cmd, start);
                                         }
                                     }
-                                    LIVE_OPEN_PLATFORM_SUPER_CHAT_DEL => {
-                                        if let Ok(pcmd) = serde_json::from_str::<
-                                            LiveOpenPlatformCmd<CSuperChatDel>,
-                                        >(
-                                            &json
-                                        ) {
-                                            for handle in cmd_handles.read().await.iter() {
-                                                let params = params.clone();
-                                                handle
-                                                    .handle_super_chat_del(
-                                                        pcmd.data.clone(),
-                                                        params,
-                                                    )
-                                                    .await;
-                                            }
+                                }
+                                LIVE_OPEN_PLATFORM_SUPER_CHAT_DEL => {
+                                    if let Ok(pcmd) = serde_json::from_str::<
+                                        LiveOpenPlatformCmd<CSuperChatDel>,
+                                    >(&json)
+                                    {
+                                        metrics::event_decoded(cmd);
+                                        for handle in cmd_handles.read().await.iter() {
+                                            let ctx = ctx.clone();
+                                            let start = Instant::now();
+                                            handle
+                                                .handle_super_chat_del(pcmd.data.clone(), ctx)
+                                                .await;
+                                            metrics::handler_latency(cmd, start);
                                         }
                                     }
-                                    LIVE_OPEN_PLATFORM_GUARD => {
-                                        if let Ok(pcmd) =
-                                            serde_json::from_str::<LiveOpenPlatformCmd<CGuard>>(
-                                                &json,
-                                            )
-                                        {
-                                            for handle in cmd_handles.read().await.iter() {
-                                                let params = params.clone();
-                                                handle
-                                                    .handle_guard(pcmd.data.clone(), params)
-                                                    .await;
-                                            }
+                                }
+                                LIVE_OPEN_PLATFORM_GUARD => {
+                                    if let Ok(pcmd) =
+                                        serde_json::from_str::<LiveOpenPlatformCmd<CGuard>>(&json)
+                                    {
+                                        metrics::event_decoded(cmd);
+                                        for handle in cmd_handles.read().await.iter() {
+                                            let ctx = ctx.clone();
+                                            let start = Instant::now();
+                                            handle.handle_guard(pcmd.data.clone(), ctx).await;
+                                            metrics::handler_latency(cmd, start);
                                         }
                                     }
-                                    LIVE_OPEN_PLATFORM_LIKE => {
-                                        if let Ok(pcmd) =
-                                            serde_json::from_str::<LiveOpenPlatformCmd<CLike>>(
-                                                &json,
-                                            )
-                                        {
-                                            for handle in cmd_handles.read().await.iter() {
-                                                let params = params.clone();
-                                                handle.handle_like(pcmd.data.clone(), params).await;
-                                            }
+                                }
+                                LIVE_OPEN_PLATFORM_LIKE => {
+                                    if let Ok(pcmd) =
+                                        serde_json::from_str::<LiveOpenPlatformCmd<CLike>>(&json)
+                                    {
+                                        metrics::event_decoded(cmd);
+                                        for handle in cmd_handles.read().await.iter() {
+                                            let ctx = ctx.clone();
+                                            let start = Instant::now();
+                                            handle.handle_like(pcmd.data.clone(), ctx).await;
+                                            metrics::handler_latency(cmd, start);
                                         }
                                     }
-                                    _ => eprintln!("Unkonw Cmd {cmd}"),
+                                }
+                                _ => {
+                                    warn!(cmd, "unknown cmd");
+                                    metrics::unknown_cmd();
                                 }
                             }
                         }
                     }
-                    Err(e) => eprintln!("Json Decode Error {e} {json}"),
-                },
-                Err(e) => eprintln!("Body From utf8 Error {e}"),
-            }
+                }
+                Err(e) => warn!(error = %e, operation = 5, body = %json, "json decode error"),
+            },
+            Err(e) => warn!(error = %e, operation = 5, "body is not utf8"),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{test_handle::TestHandler, CmdAgent, CmdAgentParams};
-    use std::sync::Arc;
+    use crate::{
+        handle,
+        handle::LiveCmdHandleOP,
+        proto::RawProto,
+        proto::CDM,
+        test_handle::TestHandler,
+        testkit::{compress, RecordingHandler},
+        CmdAgent, CmdAgentParams, SessionContext,
+    };
+    use async_trait::async_trait;
+    use std::sync::{Arc, Mutex};
     use tokio::time::Duration;
 
+    /// 记录收到的数据包operation
+    #[derive(Default)]
+    struct OpRecorder(Mutex<Vec<u32>>);
+
+    #[async_trait]
+    impl LiveCmdHandleOP for OpRecorder {
+        async fn handle(&self, proto: RawProto, _ctx: SessionContext) {
+            self.0.lock().unwrap().push(proto.operation);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_packet_dispatch() {
+        let agent = CmdAgent::new(Default::default());
+        let ops = Arc::new(OpRecorder::default());
+        let cmds = Arc::new(RecordingHandler::new());
+        agent.op_handles.write().await.push(ops.clone());
+        agent.cmd_handles.write().await.push(cmds.clone());
+        let dispatch = |bytes: Vec<u8>| {
+            handle(
+                bytes,
+                &agent.raw_handles,
+                &agent.op_handles,
+                &agent.cmd_handles,
+                SessionContext::default(),
+            )
+        };
+
+        // 压缩包解压出的多个数据包在handle返回前全部分发
+        dispatch(compress(
+            ["a", "b", "c"].map(|m| CDM::builder().msg(m).packet()),
+        ))
+        .await;
+        assert_eq!(*ops.0.lock().unwrap(), [5, 5, 5]);
+        let msgs = cmds.dms().into_iter().map(|dm| dm.msg).collect::<Vec<_>>();
+        assert_eq!(msgs, ["a", "b", "c"]);
+
+        // 未压缩的连续数据包同样拆分
+        cmds.clear();
+        dispatch(["d", "e"].map(|m| CDM::builder().msg(m).packet()).concat()).await;
+        let msgs = cmds.dms().into_iter().map(|dm| dm.msg).collect::<Vec<_>>();
+        assert_eq!(msgs, ["d", "e"]);
+    }
+
     #[tokio::test]
     async fn test_agent() {
-        let mut agent = CmdAgent::new(CmdAgentParams {
-            auth_body: "".to_string(),
+        let agent = CmdAgent::new(CmdAgentParams {
+            auth_body: "".into(),
             server_url: "".to_string(),
             ..Default::default()
         });
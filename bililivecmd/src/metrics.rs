@@ -0,0 +1,172 @@
+//! 长连接指标，启用metrics特性后注册到prometheus默认Registry
+//!
+//! 未启用时所有记录函数为空操作
+
+use std::time::Instant;
+
+#[cfg(feature = "metrics")]
+mod imp {
+    use prometheus::{
+        register_histogram_vec, register_int_counter, register_int_counter_vec, HistogramVec,
+        IntCounter, IntCounterVec,
+    };
+    use std::sync::LazyLock;
+
+    /// 收到的数据包，按operation名称分类，未知的operation归为other
+    pub static FRAMES_RECEIVED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+        register_int_counter_vec!(
+            "bililive_cmd_frames_received_total",
+            "Frames received by operation",
+            &["operation"]
+        )
+        .unwrap()
+    });
+
+    /// 解压失败的数据包
+    pub static DECOMPRESS_FAILURES: LazyLock<IntCounter> = LazyLock::new(|| {
+        register_int_counter!(
+            "bililive_cmd_decompress_failures_total",
+            "Frames failed to decompress"
+        )
+        .unwrap()
+    });
+
+    /// 解析成功的消息，按cmd分类，未知的cmd归为other
+    pub static EVENTS_DECODED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+        register_int_counter_vec!(
+            "bililive_cmd_events_decoded_total",
+            "Events decoded by cmd",
+            &["cmd"]
+        )
+        .unwrap()
+    });
+
+    /// 未知的cmd，不按cmd分类避免标签数量不受控，cmd名称见日志
+    pub static UNKNOWN_CMDS: LazyLock<IntCounter> = LazyLock::new(|| {
+        register_int_counter!("bililive_cmd_unknown_cmds_total", "Unknown cmds received").unwrap()
+    });
+
+    /// 处理对象耗时，按处理类型分类（raw/op/cmd名称/other）
+    pub static HANDLER_LATENCY: LazyLock<HistogramVec> = LazyLock::new(|| {
+        register_histogram_vec!(
+            "bililive_cmd_handler_latency_seconds",
+            "Handler latency in seconds",
+            &["handler"]
+        )
+        .unwrap()
+    });
+
+    /// 长连接重连次数
+    pub static RECONNECTS: LazyLock<IntCounter> = LazyLock::new(|| {
+        register_int_counter!("bililive_cmd_reconnects_total", "Websocket reconnects").unwrap()
+    });
+}
+
+#[cfg(feature = "metrics")]
+pub use imp::*;
+
+/// operation标签，只使用固定名称，避免服务端数据产生不受控的标签
+#[cfg(feature = "metrics")]
+fn operation_label(operation: u32) -> &'static str {
+    match operation {
+        2 => "OP_HEARTBEAT",
+        3 => "OP_HEARTBEAT_REPLY",
+        5 => "OP_SEND_SMS_REPLY",
+        7 => "OP_AUTH",
+        8 => "OP_AUTH_REPLY",
+        _ => "other",
+    }
+}
+
+/// cmd标签，只使用已知的cmd名称
+#[cfg(feature = "metrics")]
+fn cmd_label(cmd: &str) -> &'static str {
+    use crate::proto::*;
+    [
+        LIVE_OPEN_PLATFORM_DM,
+        LIVE_OPEN_PLATFORM_SEND_GIFT,
+        LIVE_OPEN_PLATFORM_SUPER_CHAT,
+        LIVE_OPEN_PLATFORM_SUPER_CHAT_DEL,
+        LIVE_OPEN_PLATFORM_GUARD,
+        LIVE_OPEN_PLATFORM_LIKE,
+    ]
+    .into_iter()
+    .find(|c| *c == cmd)
+    .unwrap_or("other")
+}
+
+pub(crate) fn frame(operation: u32) {
+    #[cfg(feature = "metrics")]
+    FRAMES_RECEIVED
+        .with_label_values(&[operation_label(operation)])
+        .inc();
+    #[cfg(not(feature = "metrics"))]
+    let _ = operation;
+}
+
+pub(crate) fn decompress_failed() {
+    #[cfg(feature = "metrics")]
+    DECOMPRESS_FAILURES.inc();
+}
+
+pub(crate) fn event_decoded(cmd: &str) {
+    #[cfg(feature = "metrics")]
+    EVENTS_DECODED.with_label_values(&[cmd_label(cmd)]).inc();
+    #[cfg(not(feature = "metrics"))]
+    let _ = cmd;
+}
+
+pub(crate) fn unknown_cmd() {
+    #[cfg(feature = "metrics")]
+    UNKNOWN_CMDS.inc();
+}
+
+pub(crate) fn handler_latency(handler: &str, start: Instant) {
+    #[cfg(feature = "metrics")]
+    {
+        let label = match handler {
+            "raw" | "op" => handler,
+            cmd => cmd_label(cmd),
+        };
+        HANDLER_LATENCY
+            .with_label_values(&[label])
+            .observe(start.elapsed().as_secs_f64());
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (handler, start);
+}
+
+pub(crate) fn reconnect() {
+    #[cfg(feature = "metrics")]
+    RECONNECTS.inc();
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cmd_metrics() {
+        frame(5);
+        frame(0xdead);
+        unknown_cmd();
+        handler_latency("dm", Instant::now());
+        assert!(
+            FRAMES_RECEIVED
+                .with_label_values(&["OP_SEND_SMS_REPLY"])
+                .get()
+                >= 1
+        );
+        assert!(FRAMES_RECEIVED.with_label_values(&["other"]).get() >= 1);
+        assert_eq!(
+            cmd_label(crate::proto::LIVE_OPEN_PLATFORM_DM),
+            "LIVE_OPEN_PLATFORM_DM"
+        );
+        assert_eq!(cmd_label("INJECTED_CMD"), "other");
+        assert!(UNKNOWN_CMDS.get() >= 1);
+        let families = prometheus::gather();
+        assert!(families
+            .iter()
+            .any(|f| f.get_name() == "bililive_cmd_handler_latency_seconds"));
+    }
+}
@@ -37,7 +37,7 @@ impl RawProto {
 impl TryFrom<Vec<u8>> for RawProto {
     fn try_from(raw: Vec<u8>) -> Result<Self, Self::Error> {
         if raw.len() < 16 {
-            println!("Error raw data:{:?}", raw);
+            tracing::warn!(len = raw.len(), "raw data shorter than header");
             return Err("Error raw data!");
         }
         let packet_length = u32::from_be_bytes(raw[0..4].try_into().unwrap());
@@ -59,6 +59,24 @@ impl TryFrom<Vec<u8>> for RawProto {
     type Error = &'static str;
 }
 
+/// 按packet_length拆分连续的数据包（如解压后的数据），不足包头长度的剩余数据将被忽略
+pub fn split_packets(bytes: &[u8]) -> Vec<&[u8]> {
+    let mut packets = Vec::new();
+    let mut offset = 0;
+    while bytes.len() - offset >= 16 {
+        let len = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        // 长度异常时剩余数据作为一个数据包
+        let end = if len < 16 || offset + len > bytes.len() {
+            bytes.len()
+        } else {
+            offset + len
+        };
+        packets.push(&bytes[offset..end]);
+        offset = end;
+    }
+    packets
+}
+
 impl From<RawProto> for Vec<u8> {
     fn from(mut p: RawProto) -> Self {
         p.packet_length = 16 + p.body.len() as u32;
@@ -99,6 +117,10 @@ pub struct CDM {
     pub fans_medal_level: i64,           // 对应房间勋章信息
     pub emoji_img_url: String,           // 表情包图片地址
     pub dm_type: i64,                    // 弹幕类型 0：普通弹幕 1：表情包弹幕
+    #[serde(default)]
+    pub open_id: String, // 用户open_id
+    #[serde(default)]
+    pub union_id: String, // 用户union_id
 }
 
 pub const LIVE_OPEN_PLATFORM_SEND_GIFT: &str = "LIVE_OPEN_PLATFORM_SEND_GIFT";
@@ -124,6 +146,10 @@ pub struct CSendGift {
     pub gift_icon: String,               // 道具icon
     pub combo_gift: bool,                // 是否是combo道具
     pub combo_info: CComboInfo,          // 结构体 连击信息
+    #[serde(default)]
+    pub open_id: String, // 送礼用户open_id
+    #[serde(default)]
+    pub union_id: String, // 送礼用户union_id
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -131,6 +157,10 @@ pub struct CAnchorInfo {
     pub uid: i64,      // 收礼主播uid
     pub uname: String, // 收礼主播昵称
     pub uface: String, // 收礼主播头像
+    #[serde(default)]
+    pub open_id: String, // 收礼主播open_id
+    #[serde(default)]
+    pub union_id: String, // 收礼主播union_id
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -160,6 +190,10 @@ pub struct CSuperChat {
     pub fans_medal_name: String,         // 对应房间勋章名字
     pub fans_medal_wearing_status: bool, // 该房间粉丝勋章佩戴情况
     pub msg_id: String,                  // 消息唯一id
+    #[serde(default)]
+    pub open_id: String, // 购买用户open_id
+    #[serde(default)]
+    pub union_id: String, // 购买用户union_id
 }
 
 pub const LIVE_OPEN_PLATFORM_SUPER_CHAT_DEL: &str = "LIVE_OPEN_PLATFORM_SUPER_CHAT_DEL";
@@ -192,6 +226,10 @@ pub struct CUserInfo {
     pub uid: i64,      // 用户uid
     pub uname: String, // 用户昵称
     pub uface: String, // 用户头像
+    #[serde(default)]
+    pub open_id: String, // 用户open_id
+    #[serde(default)]
+    pub union_id: String, // 用户union_id
 }
 
 pub const LIVE_OPEN_PLATFORM_LIKE: &str = "LIVE_OPEN_PLATFORM_LIKE";
@@ -208,6 +246,86 @@ pub struct CLike {
     pub fans_medal_wearing_status: bool, // 该房间粉丝勋章佩戴情况
     pub fans_medal_name: String,         // 粉丝勋章名
     pub fans_medal_level: i64,           // 对应房间勋章信息
+    #[serde(default)]
+    pub open_id: String, // 用户open_id
+    #[serde(default)]
+    pub union_id: String, // 用户union_id
+}
+
+/// 用户标识，open_id优先（uid已废弃，新数据中为0）
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UserId {
+    OpenId(String),
+    Uid(i64),
+    Unknown,
+}
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UserId::OpenId(open_id) => write!(f, "open_id:{}", open_id),
+            UserId::Uid(uid) => write!(f, "uid:{}", uid),
+            UserId::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// 带用户身份的消息
+pub trait UserIdentity {
+    fn uid(&self) -> i64;
+    fn open_id(&self) -> &str;
+    fn union_id(&self) -> &str;
+
+    /// 有open_id时使用open_id，否则使用非0的uid
+    fn user_id(&self) -> UserId {
+        if !self.open_id().is_empty() {
+            UserId::OpenId(self.open_id().to_string())
+        } else if self.uid() != 0 {
+            UserId::Uid(self.uid())
+        } else {
+            UserId::Unknown
+        }
+    }
+}
+
+macro_rules! user_identity {
+    ($($target:ty),*) => {
+        $(
+            impl UserIdentity for $target {
+                fn uid(&self) -> i64 {
+                    self.uid
+                }
+                fn open_id(&self) -> &str {
+                    &self.open_id
+                }
+                fn union_id(&self) -> &str {
+                    &self.union_id
+                }
+            }
+        )*
+    };
+}
+
+user_identity!(
+    CDM,
+    CSendGift,
+    CAnchorInfo,
+    CSuperChat,
+    CUserInfo,
+    CLike,
+    crate::AnchorContext
+);
+
+impl UserIdentity for CGuard {
+    fn uid(&self) -> i64 {
+        self.user_info.uid
+    }
+    fn open_id(&self) -> &str {
+        &self.user_info.open_id
+    }
+    fn union_id(&self) -> &str {
+        &self.user_info.union_id
+    }
 }
 
 #[cfg(test)]
@@ -295,4 +413,17 @@ mod tests {
         let data = serde_json::to_string_pretty(&data).unwrap();
         println!("{}", data);
     }
+
+    #[test]
+    fn test_user_id() {
+        let dm: CDM = serde_json::from_str(r#"{"uname":"a","uid":1,"uface":"","timestamp":0,"room_id":0,"msg":"","msg_id":"","guard_level":0,"fans_medal_wearing_status":false,"fans_medal_name":"","fans_medal_level":0,"emoji_img_url":"","dm_type":0}"#).unwrap();
+        assert_eq!(dm.user_id(), UserId::Uid(1));
+        let guard: CGuard = serde_json::from_str(r#"{"user_info":{"uid":0,"open_id":"abc","union_id":"u","uname":"","uface":""},"guard_level":3,"guard_num":1,"guard_unit":"","fans_medal_level":0,"fans_medal_name":"","fans_medal_wearing_status":false,"room_id":0,"msg_id":"","timestamp":0}"#).unwrap();
+        assert_eq!(guard.user_id(), UserId::OpenId("abc".to_string()));
+        assert_eq!(guard.union_id(), "u");
+        assert_eq!(guard.user_id().to_string(), "open_id:abc");
+        assert_eq!(CLike::default().user_id(), UserId::Unknown);
+        // 仅open_id/union_id可缺省，其它字段缺失时解析失败
+        assert!(serde_json::from_str::<CDM>(r#"{"uname":"a","uid":1}"#).is_err());
+    }
 }
@@ -0,0 +1,464 @@
+use crate::{handle::LiveCmdHandleRAW, SessionContext};
+use async_trait::async_trait;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Mutex, OnceLock,
+    },
+    thread::{self, JoinHandle},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing::warn;
+
+/// 录制文件标识
+pub const CAPTURE_MAGIC: &[u8; 4] = b"BLRC";
+/// 录制文件格式版本
+pub const CAPTURE_VERSION: u16 = 1;
+/// 录制文件扩展名
+pub const CAPTURE_EXTENSION: &str = "blrc";
+/// 文件头长度：标识(4) + 版本(2) + 保留(2)
+pub const CAPTURE_HEADER_LEN: usize = 8;
+/// 记录头长度：接收时间戳毫秒(8) + 数据长度(4)
+pub const RECORD_HEADER_LEN: usize = 12;
+/// 单个录制文件默认大小上限
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+/// 单条记录数据长度上限，与长连接的消息大小上限一致，读取时超出视为文件损坏
+pub const MAX_FRAME_LEN: usize = 64 * 1024 * 1024;
+
+/// 录制的原始数据包
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// 接收时间，Unix时间戳毫秒
+    pub timestamp_ms: u64,
+    pub bytes: Vec<u8>,
+}
+
+impl Frame {
+    pub fn now(bytes: Vec<u8>) -> Self {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Self {
+            timestamp_ms,
+            bytes,
+        }
+    }
+}
+
+/// 写入队列长度，队列已满时丢弃新的数据包
+pub const DEFAULT_QUEUE_CAPACITY: usize = 4096;
+
+/// 原始数据录制，将收到的每个数据包追加写入录制文件
+///
+/// 文件写入在独立线程中执行，不阻塞长连接的读取任务。
+/// 文件超过大小上限后切换到新文件，文件名为capture-{开始时间戳毫秒}-{序号}.blrc
+pub struct FrameRecorder {
+    dir: PathBuf,
+    max_file_size: u64,
+    max_files: Option<usize>,
+    queue_capacity: usize,
+    sender: OnceLock<SyncSender<Command>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+}
+
+enum Command {
+    Frame(Frame),
+    Flush(SyncSender<io::Result<()>>),
+}
+
+/// 写入线程持有的文件状态
+struct RecorderWriter {
+    dir: PathBuf,
+    max_file_size: u64,
+    max_files: Option<usize>,
+    writer: Option<BufWriter<File>>,
+    size: u64,
+    started_ms: u64,
+    seq: u32,
+}
+
+impl FrameRecorder {
+    /// 在目录中录制，目录不存在时创建
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_files: None,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            sender: OnceLock::new(),
+            writer: Mutex::new(None),
+        })
+    }
+
+    /// 设置单个文件大小上限
+    pub fn with_max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// 设置保留的文件数量，超出时删除最早的文件
+    pub fn with_max_files(mut self, max_files: usize) -> Self {
+        self.max_files = Some(max_files.max(1));
+        self
+    }
+
+    /// 设置写入队列长度
+    pub fn with_queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.queue_capacity = queue_capacity.max(1);
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// 写入一条记录，队列已满时等待
+    pub fn record(&self, frame: &Frame) -> io::Result<()> {
+        self.sender()?
+            .send(Command::Frame(frame.clone()))
+            .map_err(|_| writer_closed())
+    }
+
+    /// 等待队列中的记录全部写入文件，返回写入过程中的第一个错误
+    pub fn flush(&self) -> io::Result<()> {
+        let (tx, rx) = mpsc::sync_channel(1);
+        self.sender()?
+            .send(Command::Flush(tx))
+            .map_err(|_| writer_closed())?;
+        rx.recv().map_err(|_| writer_closed())?
+    }
+
+    /// 首次写入时启动写入线程
+    fn sender(&self) -> io::Result<&SyncSender<Command>> {
+        if let Some(sender) = self.sender.get() {
+            return Ok(sender);
+        }
+        let mut writer = self.writer.lock().unwrap();
+        if let Some(sender) = self.sender.get() {
+            return Ok(sender);
+        }
+        let (tx, rx) = mpsc::sync_channel(self.queue_capacity);
+        let state = RecorderWriter {
+            dir: self.dir.clone(),
+            max_file_size: self.max_file_size,
+            max_files: self.max_files,
+            writer: None,
+            size: 0,
+            started_ms: Frame::now(Vec::new()).timestamp_ms,
+            seq: 0,
+        };
+        let handle = thread::Builder::new()
+            .name("frame-recorder".to_string())
+            .spawn(move || state.run(rx))?;
+        *writer = Some(handle);
+        Ok(self.sender.get_or_init(|| tx))
+    }
+}
+
+impl Drop for FrameRecorder {
+    fn drop(&mut self) {
+        // 关闭队列，等待剩余记录写入
+        drop(self.sender.take());
+        if let Some(handle) = self.writer.get_mut().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// 录制文件名，序号补齐为u32的最大位数，按文件名排序即为录制顺序
+fn capture_name(started_ms: u64, seq: u32) -> String {
+    format!("capture-{}-{:010}.{}", started_ms, seq, CAPTURE_EXTENSION)
+}
+
+fn writer_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "recorder writer stopped")
+}
+
+impl RecorderWriter {
+    fn run(mut self, rx: Receiver<Command>) {
+        let mut error = None;
+        while let Ok(command) = rx.recv() {
+            let mut next = Some(command);
+            // 处理队列中的全部记录后再刷新文件
+            while let Some(command) = next.take() {
+                match command {
+                    Command::Frame(frame) => {
+                        if let Err(e) = self.write(&frame) {
+                            warn!(error = %e, dir = ?self.dir, "failed to record frame");
+                            error.get_or_insert(e);
+                        }
+                    }
+                    Command::Flush(reply) => {
+                        let res = self.flush().and(error.take().map_or(Ok(()), Err));
+                        let _ = reply.send(res);
+                    }
+                }
+                next = rx.try_recv().ok();
+            }
+            if let Err(e) = self.flush() {
+                warn!(error = %e, dir = ?self.dir, "failed to flush capture");
+            }
+        }
+    }
+
+    fn write(&mut self, frame: &Frame) -> io::Result<()> {
+        if frame.bytes.len() > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "frame length {} exceeds {}",
+                    frame.bytes.len(),
+                    MAX_FRAME_LEN
+                ),
+            ));
+        }
+        let len = (RECORD_HEADER_LEN + frame.bytes.len()) as u64;
+        if self.writer.is_none() || self.size + len > self.max_file_size {
+            self.rotate()?;
+        }
+        let writer = self.writer.as_mut().unwrap();
+        writer.write_all(&frame.timestamp_ms.to_be_bytes())?;
+        writer.write_all(&(frame.bytes.len() as u32).to_be_bytes())?;
+        writer.write_all(&frame.bytes)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        self.seq += 1;
+        let path = self.dir.join(capture_name(self.started_ms, self.seq));
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(path)?;
+        let mut writer = BufWriter::new(file);
+        writer.write_all(CAPTURE_MAGIC)?;
+        writer.write_all(&CAPTURE_VERSION.to_be_bytes())?;
+        writer.write_all(&[0, 0])?;
+        self.writer = Some(writer);
+        self.size = CAPTURE_HEADER_LEN as u64;
+        if let Some(max_files) = self.max_files {
+            let files = list_captures(&self.dir)?;
+            if files.len() > max_files {
+                for old in &files[..files.len() - max_files] {
+                    fs::remove_file(old)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LiveCmdHandleRAW for FrameRecorder {
+    async fn handle(&self, bytes: Vec<u8>, ctx: SessionContext) {
+        let res = self.sender().and_then(|sender| {
+            match sender.try_send(Command::Frame(Frame::now(bytes))) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(_)) => Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "recorder queue full, frame dropped",
+                )),
+                Err(TrySendError::Disconnected(_)) => Err(writer_closed()),
+            }
+        });
+        if let Err(e) = res {
+            warn!(error = %e, game_id = %ctx.game_id, dir = ?self.dir, "failed to record frame");
+        }
+    }
+}
+
+/// 目录中的录制文件，按文件名（即录制顺序）排序
+pub fn list_captures(dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let mut files = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == CAPTURE_EXTENSION))
+        .collect::<Vec<PathBuf>>();
+    files.sort();
+    Ok(files)
+}
+
+/// 录制文件读取
+pub struct FrameReader<R: Read> {
+    reader: R,
+}
+
+impl FrameReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> FrameReader<R> {
+    /// 读取并校验文件头
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; CAPTURE_HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        if &header[0..4] != CAPTURE_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a capture file",
+            ));
+        }
+        let version = u16::from_be_bytes([header[4], header[5]]);
+        if version != CAPTURE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported capture version {}", version),
+            ));
+        }
+        Ok(Self { reader })
+    }
+
+    fn read_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        // 文件在记录边界结束
+        match self.reader.read(&mut header[..1])? {
+            0 => return Ok(None),
+            _ => {
+                if !self.read_record(&mut header[1..])? {
+                    return Ok(None);
+                }
+            }
+        }
+        let timestamp_ms = u64::from_be_bytes(header[0..8].try_into().unwrap());
+        let len = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("record length {} exceeds {}", len, MAX_FRAME_LEN),
+            ));
+        }
+        let mut bytes = vec![0u8; len];
+        if !self.read_record(&mut bytes)? {
+            return Ok(None);
+        }
+        Ok(Some(Frame {
+            timestamp_ms,
+            bytes,
+        }))
+    }
+
+    /// 读取记录内容，最后一条记录不完整（如录制进程异常退出）时视为文件结束
+    fn read_record(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                warn!("capture ends with a truncated record");
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<R: Read> Iterator for FrameReader<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_rotate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let recorder = FrameRecorder::new(dir)
+            .unwrap()
+            .with_max_file_size(64)
+            .with_max_files(2);
+        for i in 0..5u8 {
+            let frame = Frame {
+                timestamp_ms: i as u64,
+                bytes: vec![i; 20],
+            };
+            recorder.record(&frame).unwrap();
+        }
+        recorder.flush().unwrap();
+        // 每个文件可写入1条记录，仅保留最后2个文件
+        let files = list_captures(dir).unwrap();
+        assert_eq!(files.len(), 2);
+        let frames = files
+            .iter()
+            .flat_map(|f| FrameReader::open(f).unwrap())
+            .collect::<io::Result<Vec<Frame>>>()
+            .unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].timestamp_ms, 3);
+        assert_eq!(frames[1].bytes, vec![4; 20]);
+
+        let invalid = FrameReader::new(&b"NOTACAPTURE"[..]);
+        assert!(invalid.is_err());
+
+        // 最后一条记录不完整
+        let mut capture = CAPTURE_MAGIC.to_vec();
+        capture.extend(CAPTURE_VERSION.to_be_bytes());
+        capture.extend([0, 0]);
+        capture.extend(1u64.to_be_bytes());
+        capture.extend(2u32.to_be_bytes());
+        capture.extend([7, 7]);
+        capture.extend(2u64.to_be_bytes());
+        capture.extend(10u32.to_be_bytes());
+        capture.extend([8; 3]);
+        let frames = FrameReader::new(&capture[..])
+            .unwrap()
+            .collect::<io::Result<Vec<Frame>>>()
+            .unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].bytes, vec![7, 7]);
+
+        // 长度字段损坏
+        let mut capture = capture[..CAPTURE_HEADER_LEN].to_vec();
+        capture.extend(1u64.to_be_bytes());
+        capture.extend(u32::MAX.to_be_bytes());
+        capture.extend([7, 7]);
+        let err = FrameReader::new(&capture[..])
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        assert!(capture_name(1, 9999) < capture_name(1, 10000));
+        assert!(capture_name(1, u32::MAX - 1) < capture_name(1, u32::MAX));
+    }
+
+    #[tokio::test]
+    async fn test_record_handler() {
+        let tmp = tempfile::tempdir().unwrap();
+        let recorder = FrameRecorder::new(tmp.path()).unwrap();
+        for i in 0..3u8 {
+            LiveCmdHandleRAW::handle(&recorder, vec![i; 4], SessionContext::default()).await;
+        }
+        // 释放时等待队列写入完成
+        drop(recorder);
+        let files = list_captures(tmp.path()).unwrap();
+        let frames = FrameReader::open(&files[0])
+            .unwrap()
+            .collect::<io::Result<Vec<Frame>>>()
+            .unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[2].bytes, vec![2; 4]);
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{
+    handle,
+    recorder::{list_captures, Frame, FrameReader},
+    CmdAgent,
+};
+use futures::{Stream, StreamExt};
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+/// 读取线程预读的数据包数量
+const READ_AHEAD: usize = 64;
+
+/// 回放速度
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReplaySpeed {
+    /// 按录制时的间隔回放
+    #[default]
+    Original,
+    /// 按倍数加速回放，如2.0为两倍速
+    Accelerated(f64),
+    /// 不等待，尽快回放
+    Max,
+}
+
+impl ReplaySpeed {
+    /// 两个数据包之间的等待时间
+    pub fn delay(&self, prev_ms: u64, timestamp_ms: u64) -> Duration {
+        let gap = Duration::from_millis(timestamp_ms.saturating_sub(prev_ms));
+        match *self {
+            ReplaySpeed::Original => gap,
+            ReplaySpeed::Accelerated(factor) if factor > 0.0 && factor.is_finite() => {
+                gap.div_f64(factor)
+            }
+            ReplaySpeed::Accelerated(_) | ReplaySpeed::Max => Duration::ZERO,
+        }
+    }
+}
+
+impl CmdAgent {
+    /// 离线回放数据包，依次经过raw、proto、cmd处理，不建立网络连接
+    ///
+    /// 使用当前参数中的场次上下文，返回回放的数据包数量
+    pub async fn replay(
+        &self,
+        frames: impl IntoIterator<Item = Frame>,
+        speed: ReplaySpeed,
+    ) -> usize {
+        let mut prev_ms = None;
+        // 数据包不会出错
+        let frames = futures::stream::iter(frames.into_iter().map(Ok));
+        self.replay_frames(frames, speed, &mut prev_ms)
+            .await
+            .unwrap_or_default()
+    }
+
+    /// 回放录制文件，在阻塞线程中逐条读取
+    pub async fn replay_file(
+        &self,
+        path: impl AsRef<Path>,
+        speed: ReplaySpeed,
+    ) -> io::Result<usize> {
+        let mut prev_ms = None;
+        let frames = read_frames(path.as_ref().to_path_buf());
+        self.replay_frames(frames, speed, &mut prev_ms).await
+    }
+
+    /// 按录制顺序回放目录中的所有录制文件，在阻塞线程中逐条读取
+    pub async fn replay_dir(&self, dir: impl AsRef<Path>, speed: ReplaySpeed) -> io::Result<usize> {
+        let dir = dir.as_ref().to_path_buf();
+        let paths = tokio::task::spawn_blocking(move || list_captures(dir))
+            .await
+            .map_err(io::Error::other)??;
+        let mut prev_ms = None;
+        let mut count = 0;
+        for path in paths {
+            count += self
+                .replay_frames(read_frames(path), speed, &mut prev_ms)
+                .await?;
+        }
+        Ok(count)
+    }
+
+    /// 按间隔回放，prev_ms为上一个数据包的时间戳，跨文件回放时保持间隔
+    async fn replay_frames(
+        &self,
+        frames: impl Stream<Item = io::Result<Frame>>,
+        speed: ReplaySpeed,
+        prev_ms: &mut Option<u64>,
+    ) -> io::Result<usize> {
+        let ctx = self.params().session;
+        let mut count = 0;
+        let mut frames = std::pin::pin!(frames);
+        while let Some(frame) = frames.next().await {
+            let frame = frame?;
+            if let Some(prev_ms) = *prev_ms {
+                let delay = speed.delay(prev_ms, frame.timestamp_ms);
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+            }
+            *prev_ms = Some(frame.timestamp_ms);
+            handle(
+                frame.bytes,
+                &self.raw_handles,
+                &self.op_handles,
+                &self.cmd_handles,
+                ctx.clone(),
+            )
+            .await;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// 在阻塞线程中读取录制文件，避免文件读取阻塞运行时
+///
+/// 回放提前结束时通道关闭，读取线程随之退出
+fn read_frames(path: PathBuf) -> impl Stream<Item = io::Result<Frame>> {
+    let (tx, rx) = mpsc::channel(READ_AHEAD);
+    tokio::task::spawn_blocking(move || {
+        let reader = match FrameReader::open(path) {
+            Ok(reader) => reader,
+            Err(e) => {
+                let _ = tx.blocking_send(Err(e));
+                return;
+            }
+        };
+        for frame in reader {
+            if tx.blocking_send(frame).is_err() {
+                return;
+            }
+        }
+    });
+    futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|frame| (frame, rx))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proto::CDM,
+        recorder::FrameRecorder,
+        testkit::{compress, RecordingHandler},
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_replay_speed() {
+        assert_eq!(
+            ReplaySpeed::Original.delay(1000, 3000),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            ReplaySpeed::Accelerated(4.0).delay(1000, 3000),
+            Duration::from_millis(500)
+        );
+        assert_eq!(ReplaySpeed::Max.delay(1000, 3000), Duration::ZERO);
+        assert_eq!(ReplaySpeed::Original.delay(3000, 1000), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_replay_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = FrameRecorder::new(dir.path()).unwrap();
+        recorder
+            .record(&Frame {
+                timestamp_ms: 0,
+                bytes: CDM::builder().msg("a").packet(),
+            })
+            .unwrap();
+        // 压缩包内包含多个连续数据包
+        recorder
+            .record(&Frame {
+                timestamp_ms: 20,
+                bytes: compress([
+                    CDM::builder().msg("b").packet(),
+                    CDM::builder().msg("c").packet(),
+                ]),
+            })
+            .unwrap();
+        recorder.flush().unwrap();
+
+        let agent = CmdAgent::new(Default::default());
+        let handler = Arc::new(RecordingHandler::new());
+        agent.cmd_handles.write().await.push(handler.clone());
+        let count = agent
+            .replay_dir(dir.path(), ReplaySpeed::Accelerated(2.0))
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+        let msgs = handler
+            .dms()
+            .into_iter()
+            .map(|dm| dm.msg)
+            .collect::<Vec<String>>();
+        assert_eq!(msgs, ["a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_replay_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let recorder = FrameRecorder::new(dir.path()).unwrap();
+        for (i, msg) in ["a", "b", "c"].into_iter().enumerate() {
+            recorder
+                .record(&Frame {
+                    timestamp_ms: i as u64,
+                    bytes: CDM::builder().msg(msg).packet(),
+                })
+                .unwrap();
+        }
+        recorder.flush().unwrap();
+        let path = list_captures(dir.path()).unwrap().remove(0);
+
+        let agent = CmdAgent::new(Default::default());
+        let handler = Arc::new(RecordingHandler::new());
+        agent.cmd_handles.write().await.push(handler.clone());
+        let count = agent.replay_file(&path, ReplaySpeed::Max).await.unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(handler.dms().len(), 3);
+
+        let err = agent
+            .replay_file(dir.path().join("missing"), ReplaySpeed::Max)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use zeroize::Zeroize;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// 敏感信息（如AccessKeySecret、auth_body）
+///
+/// Debug/Display输出时隐藏内容，释放时清零内存，序列化时输出原值
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// 读取原值，仅在需要使用时调用
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl From<String> for Secret<String> {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Secret<String> {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T: Zeroize> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T: Zeroize + Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Zeroize + Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Secret;
+
+    #[test]
+    fn test_secret_redact() {
+        let secret = Secret::from("token");
+        assert_eq!(format!("{:?}", secret), "[REDACTED]");
+        assert_eq!(format!("{}", secret), "[REDACTED]");
+        assert_eq!(secret.expose(), "token");
+        let json = serde_json::to_string(&secret).unwrap();
+        assert_eq!(json, r#""token""#);
+        let secret: Secret<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(secret.expose(), "token");
+    }
+}
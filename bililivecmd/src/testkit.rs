@@ -0,0 +1,456 @@
+//! 测试工具：消息构建、数据包编码及记录收到消息的处理对象
+//!
+//! 下游可用于单元测试自己的LiveCmdHandle实现，不需要真实的长连接
+
+use crate::{
+    handle::LiveCmdHandle, proto::*, recorder::Frame, replay::ReplaySpeed, CmdAgent, SessionContext,
+};
+use async_trait::async_trait;
+use flate2::{write::ZlibEncoder, Compression};
+use serde::Serialize;
+use std::{io::Write, sync::Mutex, time::Duration};
+use tokio::sync::Notify;
+
+/// 带cmd名称的消息
+pub trait LiveCmd: Serialize + Default + Clone {
+    const CMD: &'static str;
+}
+
+impl LiveCmd for CDM {
+    const CMD: &'static str = LIVE_OPEN_PLATFORM_DM;
+}
+impl LiveCmd for CSendGift {
+    const CMD: &'static str = LIVE_OPEN_PLATFORM_SEND_GIFT;
+}
+impl LiveCmd for CSuperChat {
+    const CMD: &'static str = LIVE_OPEN_PLATFORM_SUPER_CHAT;
+}
+impl LiveCmd for CSuperChatDel {
+    const CMD: &'static str = LIVE_OPEN_PLATFORM_SUPER_CHAT_DEL;
+}
+impl LiveCmd for CGuard {
+    const CMD: &'static str = LIVE_OPEN_PLATFORM_GUARD;
+}
+impl LiveCmd for CLike {
+    const CMD: &'static str = LIVE_OPEN_PLATFORM_LIKE;
+}
+
+/// 编码为服务端推送的弹幕消息包（Proto.Operation==5）
+pub fn encode<T: LiveCmd>(data: &T) -> Vec<u8> {
+    let cmd = LiveOpenPlatformCmd {
+        cmd: T::CMD.to_string(),
+        data: data.clone(),
+    };
+    RawProto::new(5, serde_json::to_vec(&cmd).unwrap_or_default()).into()
+}
+
+/// 将多个数据包压缩为一个zlib数据包（Proto.Version==2）
+pub fn compress(packets: impl IntoIterator<Item = Vec<u8>>) -> Vec<u8> {
+    let mut z = ZlibEncoder::new(Vec::new(), Compression::default());
+    for packet in packets {
+        let _ = z.write_all(&packet);
+    }
+    let mut proto = RawProto::new(5, z.finish().unwrap_or_default());
+    proto.version = 2;
+    proto.into()
+}
+
+/// 依次经过完整的raw、proto、cmd处理，返回处理的数据包数量
+pub async fn feed(agent: &CmdAgent, packets: impl IntoIterator<Item = Vec<u8>>) -> usize {
+    agent
+        .replay(packets.into_iter().map(Frame::now), ReplaySpeed::Max)
+        .await
+}
+
+macro_rules! builder {
+    ($target:ident, $builder:ident { $($field:ident: $ty:ty),* $(,)? }) => {
+        #[doc = concat!(stringify!($target), "构建")]
+        #[derive(Debug, Clone, Default)]
+        pub struct $builder($target);
+
+        impl $target {
+            pub fn builder() -> $builder {
+                $builder::default()
+            }
+        }
+
+        impl $builder {
+            $(
+                pub fn $field(mut self, $field: impl Into<$ty>) -> Self {
+                    self.0.$field = $field.into();
+                    self
+                }
+            )*
+
+            pub fn build(self) -> $target {
+                self.0
+            }
+
+            /// 编码为弹幕消息包
+            pub fn packet(&self) -> Vec<u8> {
+                encode(&self.0)
+            }
+        }
+
+        impl From<$builder> for $target {
+            fn from(builder: $builder) -> Self {
+                builder.0
+            }
+        }
+    };
+}
+
+builder!(
+    CDM,
+    CDMBuilder {
+        uname: String,
+        uid: i64,
+        open_id: String,
+        union_id: String,
+        uface: String,
+        timestamp: i64,
+        room_id: i64,
+        msg: String,
+        msg_id: String,
+        guard_level: i64,
+        fans_medal_wearing_status: bool,
+        fans_medal_name: String,
+        fans_medal_level: i64,
+        emoji_img_url: String,
+        dm_type: i64,
+    }
+);
+
+builder!(
+    CSendGift,
+    CSendGiftBuilder {
+        room_id: i64,
+        uid: i64,
+        open_id: String,
+        union_id: String,
+        uname: String,
+        uface: String,
+        gift_id: i64,
+        gift_name: String,
+        gift_num: i64,
+        price: i64,
+        paid: bool,
+        fans_medal_level: i64,
+        fans_medal_name: String,
+        fans_medal_wearing_status: bool,
+        guard_level: i64,
+        timestamp: i64,
+        anchor_info: CAnchorInfo,
+        msg_id: String,
+        gift_icon: String,
+        combo_gift: bool,
+        combo_info: CComboInfo,
+    }
+);
+
+impl CSendGiftBuilder {
+    /// 设置连击信息，同时标记为combo道具
+    pub fn combo(mut self, combo_id: impl Into<String>, combo_count: i64) -> Self {
+        self.0.combo_gift = true;
+        self.0.combo_info = CComboInfo {
+            combo_base_num: self.0.gift_num.max(1),
+            combo_count,
+            combo_id: combo_id.into(),
+            ..self.0.combo_info
+        };
+        self
+    }
+}
+
+builder!(
+    CSuperChat,
+    CSuperChatBuilder {
+        room_id: i64,
+        uid: i64,
+        open_id: String,
+        union_id: String,
+        uname: String,
+        uface: String,
+        message_id: i64,
+        message: String,
+        rmb: i64,
+        timestamp: i64,
+        start_time: i64,
+        end_time: i64,
+        guard_level: i64,
+        fans_medal_level: i64,
+        fans_medal_name: String,
+        fans_medal_wearing_status: bool,
+        msg_id: String,
+    }
+);
+
+builder!(CSuperChatDel, CSuperChatDelBuilder {
+    room_id: i64,
+    message_ids: Vec<i64>,
+    msg_id: String,
+});
+
+builder!(
+    CGuard,
+    CGuardBuilder {
+        user_info: CUserInfo,
+        guard_level: i64,
+        guard_num: i64,
+        guard_unit: String,
+        fans_medal_level: i64,
+        fans_medal_name: String,
+        fans_medal_wearing_status: bool,
+        room_id: i64,
+        msg_id: String,
+        timestamp: i64,
+    }
+);
+
+impl CGuardBuilder {
+    pub fn uid(mut self, uid: i64) -> Self {
+        self.0.user_info.uid = uid;
+        self
+    }
+
+    pub fn open_id(mut self, open_id: impl Into<String>) -> Self {
+        self.0.user_info.open_id = open_id.into();
+        self
+    }
+
+    pub fn union_id(mut self, union_id: impl Into<String>) -> Self {
+        self.0.user_info.union_id = union_id.into();
+        self
+    }
+
+    pub fn uname(mut self, uname: impl Into<String>) -> Self {
+        self.0.user_info.uname = uname.into();
+        self
+    }
+
+    pub fn uface(mut self, uface: impl Into<String>) -> Self {
+        self.0.user_info.uface = uface.into();
+        self
+    }
+}
+
+builder!(
+    CLike,
+    CLikeBuilder {
+        uname: String,
+        uid: i64,
+        open_id: String,
+        union_id: String,
+        uface: String,
+        timestamp: i64,
+        room_id: i64,
+        like_text: String,
+        like_conut: i64,
+        fans_medal_wearing_status: bool,
+        fans_medal_name: String,
+        fans_medal_level: i64,
+    }
+);
+
+/// 收到的消息
+#[derive(Debug, Clone)]
+pub enum RecordedEvent {
+    Dm(CDM),
+    SendGift(CSendGift),
+    SuperChat(CSuperChat),
+    SuperChatDel(CSuperChatDel),
+    Guard(CGuard),
+    Like(CLike),
+}
+
+/// 记录收到的消息及场次上下文，用于断言
+#[derive(Default)]
+pub struct RecordingHandler {
+    events: Mutex<Vec<(RecordedEvent, SessionContext)>>,
+    notify: Notify,
+}
+
+impl RecordingHandler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn push(&self, event: RecordedEvent, ctx: SessionContext) {
+        self.events.lock().unwrap().push((event, ctx));
+        self.notify.notify_waiters();
+    }
+
+    /// 按收到顺序的所有消息
+    pub fn events(&self) -> Vec<RecordedEvent> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(e, _)| e.clone())
+            .collect()
+    }
+
+    /// 按收到顺序的所有场次上下文
+    pub fn contexts(&self) -> Vec<SessionContext> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, c)| c.clone())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+
+    pub fn dms(&self) -> Vec<CDM> {
+        self.filter(|e| match e {
+            RecordedEvent::Dm(c) => Some(c.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn gifts(&self) -> Vec<CSendGift> {
+        self.filter(|e| match e {
+            RecordedEvent::SendGift(c) => Some(c.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn super_chats(&self) -> Vec<CSuperChat> {
+        self.filter(|e| match e {
+            RecordedEvent::SuperChat(c) => Some(c.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn super_chat_dels(&self) -> Vec<CSuperChatDel> {
+        self.filter(|e| match e {
+            RecordedEvent::SuperChatDel(c) => Some(c.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn guards(&self) -> Vec<CGuard> {
+        self.filter(|e| match e {
+            RecordedEvent::Guard(c) => Some(c.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn likes(&self) -> Vec<CLike> {
+        self.filter(|e| match e {
+            RecordedEvent::Like(c) => Some(c.clone()),
+            _ => None,
+        })
+    }
+
+    fn filter<T>(&self, f: impl Fn(&RecordedEvent) -> Option<T>) -> Vec<T> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(e, _)| f(e))
+            .collect()
+    }
+
+    /// 等待收到至少n条消息，超时返回false
+    pub async fn wait_for(&self, n: usize, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let notified = self.notify.notified();
+                if self.len() >= n {
+                    return;
+                }
+                notified.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+}
+
+#[async_trait]
+impl LiveCmdHandle for RecordingHandler {
+    async fn handle_dm(&self, cmd: CDM, ctx: SessionContext) {
+        self.push(RecordedEvent::Dm(cmd), ctx);
+    }
+    async fn handle_send_gift(&self, cmd: CSendGift, ctx: SessionContext) {
+        self.push(RecordedEvent::SendGift(cmd), ctx);
+    }
+    async fn handle_super_chat(&self, cmd: CSuperChat, ctx: SessionContext) {
+        self.push(RecordedEvent::SuperChat(cmd), ctx);
+    }
+    async fn handle_super_chat_del(&self, cmd: CSuperChatDel, ctx: SessionContext) {
+        self.push(RecordedEvent::SuperChatDel(cmd), ctx);
+    }
+    async fn handle_guard(&self, cmd: CGuard, ctx: SessionContext) {
+        self.push(RecordedEvent::Guard(cmd), ctx);
+    }
+    async fn handle_like(&self, cmd: CLike, ctx: SessionContext) {
+        self.push(RecordedEvent::Like(cmd), ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_testkit() {
+        let agent = CmdAgent::new(Default::default());
+        let recorder = Arc::new(RecordingHandler::new());
+        agent.cmd_handles.write().await.push(recorder.clone());
+
+        let dm = CDM::builder().uname("a").msg("hi").uid(1);
+        let gift = CSendGift::builder()
+            .gift_name("小花花")
+            .gift_num(2)
+            .combo("combo", 3);
+        let count = feed(
+            &agent,
+            [
+                dm.packet(),
+                compress([
+                    gift.packet(),
+                    CGuard::builder()
+                        .uname("b")
+                        .open_id("ob")
+                        .guard_level(3)
+                        .packet(),
+                ]),
+                CSuperChatDel::builder().message_ids(vec![1, 2]).packet(),
+            ],
+        )
+        .await;
+        assert_eq!(count, 3);
+        assert!(recorder.wait_for(4, Duration::from_secs(1)).await);
+
+        let dms = recorder.dms();
+        assert_eq!((dms[0].uname.as_str(), dms[0].msg.as_str()), ("a", "hi"));
+        let gifts = recorder.gifts();
+        assert!(gifts[0].combo_gift);
+        assert_eq!(gifts[0].combo_info.combo_base_num, 2);
+        assert_eq!(gifts[0].combo_info.combo_count, 3);
+        assert_eq!(recorder.guards()[0].user_info.uname, "b");
+        assert_eq!(
+            recorder.guards()[0].user_id(),
+            UserId::OpenId("ob".to_string())
+        );
+        assert_eq!(recorder.super_chat_dels()[0].message_ids, [1, 2]);
+        assert!(recorder.likes().is_empty());
+
+        recorder.clear();
+        assert!(!recorder.wait_for(1, Duration::from_millis(10)).await);
+    }
+}
@@ -0,0 +1,74 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite每次只能修改一列
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DM::Table)
+                    .add_column(ColumnDef::new(DM::UOpenId).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DM::Table)
+                    .add_column(ColumnDef::new(DM::UUnionId).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_dm_u_open_id")
+                    .table(DM::Table)
+                    .col(DM::UOpenId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_dm_u_open_id")
+                    .table(DM::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DM::Table)
+                    .drop_column(DM::UUnionId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(DM::Table)
+                    .drop_column(DM::UOpenId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum DM {
+    Table,
+    UOpenId,
+    UUnionId,
+}
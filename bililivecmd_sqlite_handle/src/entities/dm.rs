@@ -20,6 +20,8 @@ pub struct Model {
     pub fans_medal_level: Option<i64>,
     pub emoji_img_url: Option<String>,
     pub dm_type: Option<i64>,
+    pub u_open_id: Option<String>,
+    pub u_union_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
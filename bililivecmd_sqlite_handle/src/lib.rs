@@ -1,13 +1,19 @@
 use async_trait::async_trait;
-use bililivecmd::{handle::LiveCmdHandle, proto::*, CmdAgentParams};
+use bililivecmd::{handle::LiveCmdHandle, proto::*, SessionContext};
 use entities::dm;
-use sea_orm::{ActiveModelTrait, Database, DatabaseConnection};
+use sea_orm::{
+    ActiveModelTrait, ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr, Statement,
+};
+use tracing::{debug, info, warn};
 
 pub mod entities;
 pub mod trans;
 
-fn env_connect_str() -> String {
-    dotenvy::var("DATABASE_URL").unwrap()
+/// 当前迁移版本需要的dm表字段
+const REQUIRED_DM_COLUMNS: [&str; 2] = ["u_open_id", "u_union_id"];
+
+fn env_connect_str() -> Result<String, DbErr> {
+    dotenvy::var("DATABASE_URL").map_err(|e| DbErr::Custom(format!("DATABASE_URL {}", e)))
 }
 
 #[derive(Default)]
@@ -17,53 +23,106 @@ pub struct SqliteHandler {
 }
 
 impl SqliteHandler {
-    pub async fn new(cs: Option<String>) -> Self {
+    /// 连接数据库，cs为空时读取DATABASE_URL
+    ///
+    /// 数据库需已执行全部迁移（migration up），缺少字段时返回错误
+    pub async fn new(cs: Option<String>) -> Result<Self, DbErr> {
         let cs = match cs {
             Some(s) => s,
-            None => env_connect_str(),
+            None => env_connect_str()?,
         };
-        Self {
+        Self::with_connection(Database::connect(cs).await?).await
+    }
+
+    /// 使用已建立的连接，检查表结构
+    pub async fn with_connection(db: DatabaseConnection) -> Result<Self, DbErr> {
+        check_schema(&db).await?;
+        Ok(Self {
             console_saved: false,
-            db: Database::connect(cs).await.unwrap(),
-        }
+            db,
+        })
+    }
+}
+
+async fn check_schema(db: &DatabaseConnection) -> Result<(), DbErr> {
+    if db.get_database_backend() != DbBackend::Sqlite {
+        return Ok(());
+    }
+    let rows = db
+        .query_all(Statement::from_string(
+            DbBackend::Sqlite,
+            "SELECT name FROM pragma_table_info('dm')",
+        ))
+        .await?;
+    let columns = rows
+        .iter()
+        .filter_map(|r| r.try_get::<String>("", "name").ok())
+        .collect::<Vec<String>>();
+    match REQUIRED_DM_COLUMNS
+        .iter()
+        .find(|c| !columns.iter().any(|n| n == *c))
+    {
+        Some(missing) => Err(DbErr::Custom(format!(
+            "table dm is missing column {}, run `cargo run -p migration -- up` first",
+            missing
+        ))),
+        None => Ok(()),
     }
 }
 
 #[async_trait]
 impl LiveCmdHandle for SqliteHandler {
-    async fn handle_dm(&self, cmd: CDM, _params: CmdAgentParams) {
+    async fn handle_dm(&self, cmd: CDM, _ctx: SessionContext) {
         let new: dm::ActiveModel = cmd.into();
-        let r = new.insert(&self.db).await;
-        if self.console_saved && r.is_ok() {
-            println!("saved dm:{:?}", r.unwrap());
+        match new.insert(&self.db).await {
+            Ok(saved) if self.console_saved => info!(dm = ?saved, "saved dm"),
+            Ok(_) => {}
+            Err(e) => warn!(error = %e, "failed to save dm"),
         }
     }
-    async fn handle_send_gift(&self, cmd: CSendGift, _params: CmdAgentParams) {
-        println!("handle_send_gift {:?}", cmd);
+    async fn handle_send_gift(&self, cmd: CSendGift, _ctx: SessionContext) {
+        debug!(?cmd, "handle_send_gift");
     }
-    async fn handle_super_chat(&self, cmd: CSuperChat, _params: CmdAgentParams) {
-        println!("handle_super_chat {:?}", cmd);
+    async fn handle_super_chat(&self, cmd: CSuperChat, _ctx: SessionContext) {
+        debug!(?cmd, "handle_super_chat");
     }
-    async fn handle_super_chat_del(&self, cmd: CSuperChatDel, _params: CmdAgentParams) {
-        println!("handle_super_chat_del {:?}", cmd);
+    async fn handle_super_chat_del(&self, cmd: CSuperChatDel, _ctx: SessionContext) {
+        debug!(?cmd, "handle_super_chat_del");
     }
-    async fn handle_guard(&self, cmd: CGuard, _params: CmdAgentParams) {
-        println!("handle_guard {:?}", cmd);
+    async fn handle_guard(&self, cmd: CGuard, _ctx: SessionContext) {
+        debug!(?cmd, "handle_guard");
     }
-    async fn handle_like(&self, cmd: CLike, _params: CmdAgentParams) {
-        println!("handle_like {:?}", cmd);
+    async fn handle_like(&self, cmd: CLike, _ctx: SessionContext) {
+        debug!(?cmd, "handle_like");
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use sea_orm::{ActiveModelTrait, Database};
+    use sea_orm::{ActiveModelTrait, ConnectionTrait, Database};
+
+    use crate::{entities::dm, env_connect_str, SqliteHandler};
 
-    use crate::{entities::dm, env_connect_str};
+    #[tokio::test]
+    async fn test_check_schema() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        db.execute_unprepared("CREATE TABLE dm (id INTEGER PRIMARY KEY, u_name TEXT)")
+            .await
+            .unwrap();
+        let err = SqliteHandler::with_connection(db.clone()).await.err();
+        assert!(err.unwrap().to_string().contains("u_open_id"));
+        db.execute_unprepared("ALTER TABLE dm ADD COLUMN u_open_id TEXT")
+            .await
+            .unwrap();
+        db.execute_unprepared("ALTER TABLE dm ADD COLUMN u_union_id TEXT")
+            .await
+            .unwrap();
+        assert!(SqliteHandler::with_connection(db).await.is_ok());
+    }
 
     #[tokio::test]
     async fn test_save() {
-        let cs = env_connect_str();
+        let cs = env_connect_str().unwrap();
         println!("{}", cs);
         let db = Database::connect(cs).await.unwrap();
         for i in 1..10usize {
@@ -19,6 +19,8 @@ impl From<CDM> for dm::ActiveModel {
             fans_medal_level: Set(Some(v.fans_medal_level)),
             emoji_img_url: Set(Some(v.emoji_img_url)),
             dm_type: Set(Some(v.dm_type)),
+            u_open_id: Set(Some(v.open_id)),
+            u_union_id: Set(Some(v.union_id)),
             ..Default::default()
         }
     }